@@ -0,0 +1,90 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::prelude::*;
+
+use crate::cell::Cell;
+use crate::ports::Output;
+
+// renders machine output as text: values 0..127 are characters, anything
+// else is shown as a number on a line of its own
+pub struct AsciiOutput {
+    line_start: bool,
+}
+
+impl AsciiOutput {
+    pub fn new() -> Self {
+        AsciiOutput { line_start: true }
+    }
+
+    pub fn render<C: Cell>(&mut self, value: &C) -> String {
+        match value.to_isize() {
+            Some(c @ 0..=127) => {
+                self.line_start = c == '\n' as isize;
+                (c as u8 as char).to_string()
+            }
+            _ => {
+                let res = if self.line_start {
+                    format!("{}\n", value)
+                } else {
+                    format!("\n{}\n", value)
+                };
+                self.line_start = true;
+                res
+            }
+        }
+    }
+}
+
+impl<C: Cell> Output<C> for AsciiOutput {
+    fn write(&mut self, value: C) {
+        print!("{}", self.render(&value));
+    }
+}
+
+// lines from stdin as ASCII codes, each followed by a newline. a line with
+// other characters is reported on stderr and skipped
+pub struct AsciiInput<C> {
+    pending: VecDeque<C>,
+}
+
+impl<C> AsciiInput<C> {
+    pub fn new() -> Self {
+        AsciiInput { pending: VecDeque::new() }
+    }
+}
+
+impl<C: Cell> Iterator for AsciiInput<C> {
+    type Item = C;
+
+    fn next(&mut self) -> Option<C> {
+        while self.pending.is_empty() {
+            // the program's prompt usually doesn't end in a newline
+            io::stdout().flush().ok()?;
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line).ok()? == 0 {
+                return None;
+            }
+            match encode_line(&line) {
+                Ok(codes) => self.pending.extend(codes),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        self.pending.pop_front()
+    }
+}
+
+// turns a typed line into ASCII codes followed by a newline
+pub fn encode_line<C: Cell>(line: &str) -> io::Result<Vec<C>> {
+    let line = line.trim_end_matches(&['\r', '\n'][..]);
+    if let Some(c) = line.chars().find(|c| !c.is_ascii()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{:?} is not an ASCII character", c),
+        ));
+    }
+    Ok(line
+        .bytes()
+        .chain(Some(b'\n'))
+        .map(|b| C::from_isize(b as isize))
+        .collect())
+}
@@ -0,0 +1,71 @@
+// async versions of the ports in ports.rs, for running many machines on one
+// thread: a machine waiting for input is a pending future rather than a
+// blocked thread. nothing here knows about an executor, executor.rs has a
+// small one and channel.rs the queues that connect machines
+use core::future::{poll_fn, Future};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::cell::Cell;
+use crate::vm::*;
+
+// instructions a machine runs before it lets the other tasks have a go
+const BUDGET: u64 = 10_000;
+
+pub trait AsyncInput<C> {
+    // Ready(None) once nothing more will come
+    fn poll_read(&mut self, cx: &mut Context) -> Poll<Option<C>>;
+}
+
+pub trait AsyncOutput<C> {
+    // Ready when the sink can take another value
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<()>;
+    fn write(&mut self, value: C);
+}
+
+// runs until the program halts or the input is closed while the machine
+// waits for it. every output is written before the next instruction runs
+pub async fn run_async<C: Cell>(
+    program: &mut IntCodeProgram<C>,
+    mut input: impl AsyncInput<C>,
+    mut output: impl AsyncOutput<C>,
+) -> Result<State, Fault> {
+    let mut steps = 0u64;
+    loop {
+        let state = program.step()?;
+        while let Some(value) = program.take_output() {
+            poll_fn(|cx| output.poll_ready(cx)).await;
+            output.write(value);
+        }
+        match state {
+            State::Running => {
+                steps += 1;
+                if steps.is_multiple_of(BUDGET) {
+                    YieldNow(false).await;
+                }
+            }
+            State::WaitingForInput => match poll_fn(|cx| input.poll_read(cx)).await {
+                Some(value) => program.add_input(value),
+                None => return Ok(State::WaitingForInput),
+            },
+            State::Halted => return Ok(State::Halted),
+        }
+    }
+}
+
+// pending once, so a long running machine doesn't keep the others from
+// running on a single threaded executor
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
@@ -0,0 +1,176 @@
+use std::fs;
+use std::io;
+use std::thread;
+
+use crate::host::*;
+use crate::vm::*;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Csv,
+    Jsonl,
+}
+
+struct Row {
+    status: &'static str,
+    steps: usize,
+    outputs: Vec<isize>,
+    error: Option<String>,
+}
+
+fn run_row(program: &IntCodeProgram, inputs: &[isize], max_steps: usize) -> Row {
+    let mut program = program.clone();
+    program.input = inputs.to_vec();
+    let mut steps = 0;
+    let (status, error) = loop {
+        if steps == max_steps {
+            break ("step limit", None);
+        }
+        // like recorded sessions, halt and the faulting instruction count
+        let state = program.step();
+        if state != Ok(State::WaitingForInput) {
+            steps += 1;
+        }
+        match state {
+            Ok(State::Running) => {}
+            Ok(State::Halted) => break ("halted", None),
+            Ok(State::WaitingForInput) => break ("needs input", None),
+            Err(fault) => break ("fault", Some(fault.to_string())),
+        }
+    };
+    Row {
+        status,
+        steps,
+        outputs: program.output,
+        error,
+    }
+}
+
+fn parse_numbers(s: &str) -> Option<Vec<isize>> {
+    if s.trim().is_empty() {
+        return Some(Vec::new());
+    }
+    s.split(',').map(|v| v.trim().parse::<isize>().ok()).collect()
+}
+
+// one input vector per line: `1,5` for csv, `[1, 5]` for jsonl. a csv
+// header line is skipped
+fn parse_inputs(text: &str, format: Format) -> io::Result<Vec<Vec<isize>>> {
+    let mut rows = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let values = match format {
+            Format::Csv => parse_numbers(line),
+            Format::Jsonl => line
+                .trim()
+                .strip_prefix('[')
+                .and_then(|l| l.strip_suffix(']'))
+                .and_then(parse_numbers),
+        };
+        match values {
+            Some(values) => rows.push(values),
+            None if format == Format::Csv && n == 0 => {}
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: expected a list of integers, got {}", n + 1, line),
+                ))
+            }
+        }
+    }
+    Ok(rows)
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains(',') || s.contains('"') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn json_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn print_row(i: usize, row: &Row, format: Format) {
+    match format {
+        Format::Csv => {
+            let outputs = row.outputs.iter().map(|o| o.to_string()).collect::<Vec<String>>();
+            println!(
+                "{},{},{},{},{}",
+                i + 1,
+                row.status,
+                row.steps,
+                outputs.join(" "),
+                csv_field(row.error.as_deref().unwrap_or_default())
+            );
+        }
+        Format::Jsonl => {
+            let outputs = row.outputs.iter().map(|o| o.to_string()).collect::<Vec<String>>();
+            println!(
+                "{{\"row\":{},\"status\":{},\"steps\":{},\"outputs\":[{}],\"error\":{}}}",
+                i + 1,
+                json_string(row.status),
+                row.steps,
+                outputs.join(","),
+                row.error.as_deref().map_or("null".to_string(), json_string)
+            );
+        }
+    }
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut inputs_file = None;
+    let mut format = None;
+    let mut max_steps = 1_000_000;
+    let mut threads = thread::available_parallelism().map_or(4, |n| n.get());
+    let mut filename = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let number = |s: String| s.parse::<usize>().map_err(|_| invalid(format!("bad number {}", s)));
+        match arg.as_str() {
+            "--inputs" => inputs_file = Some(value(&mut iter, arg)?),
+            "--format" => {
+                format = match value(&mut iter, arg)?.as_str() {
+                    "csv" => Some(Format::Csv),
+                    "jsonl" => Some(Format::Jsonl),
+                    f => return Err(invalid(format!("unknown format {}, use csv or jsonl", f))),
+                }
+            }
+            "--max-steps" => max_steps = number(value(&mut iter, arg)?)?,
+            "--threads" => threads = number(value(&mut iter, arg)?)?.max(1),
+            _ => filename = Some(arg.as_str()),
+        }
+    }
+
+    let inputs_file = inputs_file.ok_or_else(|| invalid("missing --inputs".to_string()))?;
+    // the format follows the file extension unless given
+    let format = format.unwrap_or(if inputs_file.ends_with(".jsonl") { Format::Jsonl } else { Format::Csv });
+    let rows = parse_inputs(&fs::read_to_string(&inputs_file)?, format)?;
+    let program = load_program(filename)?;
+
+    // every row gets a fresh copy of the program, rows are split between threads
+    let chunk = rows.len().div_ceil(threads).max(1);
+    let results = thread::scope(|s| {
+        let handles = rows
+            .chunks(chunk)
+            .map(|rows| {
+                let program = &program;
+                s.spawn(move || rows.iter().map(|inputs| run_row(program, inputs, max_steps)).collect::<Vec<Row>>())
+            })
+            .collect::<Vec<_>>();
+        handles.into_iter().flat_map(|h| h.join().unwrap()).collect::<Vec<Row>>()
+    });
+
+    if format == Format::Csv {
+        println!("row,status,steps,outputs,error");
+    }
+    for (i, row) in results.iter().enumerate() {
+        print_row(i, row, format);
+    }
+    Ok(())
+}
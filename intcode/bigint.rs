@@ -0,0 +1,286 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::error::Error;
+use core::fmt;
+use core::str::FromStr;
+
+// arbitrary precision integer, sign and magnitude in base 2^32 with the
+// least significant digit first. zero has no digits and is never negative
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct BigInt {
+    negative: bool,
+    digits: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn from_i128(value: i128) -> Self {
+        let mut magnitude = value.unsigned_abs();
+        let mut digits = Vec::new();
+        while magnitude != 0 {
+            digits.push(magnitude as u32);
+            magnitude >>= 32;
+        }
+        BigInt {
+            negative: value < 0,
+            digits,
+        }
+    }
+
+    pub fn to_i128(&self) -> Option<i128> {
+        if self.digits.len() > 4 {
+            return None;
+        }
+        let magnitude = self.digits.iter().rev().fold(0u128, |acc, &d| (acc << 32) | d as u128);
+        if self.negative {
+            0i128.checked_sub_unsigned(magnitude)
+        } else {
+            Some(magnitude as i128).filter(|&m| m >= 0)
+        }
+    }
+
+    // sign and magnitude, least significant digit first
+    pub fn from_digits(negative: bool, digits: Vec<u32>) -> Self {
+        BigInt { negative, digits }.normalize()
+    }
+
+    pub fn digits(&self) -> (bool, &[u32]) {
+        (self.negative, &self.digits)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    fn normalize(mut self) -> Self {
+        while self.digits.last() == Some(&0) {
+            self.digits.pop();
+        }
+        if self.digits.is_empty() {
+            self.negative = false;
+        }
+        self
+    }
+
+    pub fn add(&self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt {
+                negative: self.negative,
+                digits: add_digits(&self.digits, &other.digits),
+            }
+            .normalize();
+        }
+        // different signs, the larger magnitude decides the sign
+        match cmp_digits(&self.digits, &other.digits) {
+            Ordering::Less => BigInt {
+                negative: other.negative,
+                digits: sub_digits(&other.digits, &self.digits),
+            },
+            _ => BigInt {
+                negative: self.negative,
+                digits: sub_digits(&self.digits, &other.digits),
+            },
+        }
+        .normalize()
+    }
+
+    pub fn mul(&self, other: &BigInt) -> BigInt {
+        let mut digits = vec![0u32; self.digits.len() + other.digits.len()];
+        for (i, &a) in self.digits.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.digits.iter().enumerate() {
+                let t = a as u64 * b as u64 + digits[i + j] as u64 + carry;
+                digits[i + j] = t as u32;
+                carry = t >> 32;
+            }
+            digits[i + other.digits.len()] = carry as u32;
+        }
+        BigInt {
+            negative: self.negative != other.negative,
+            digits,
+        }
+        .normalize()
+    }
+
+    // divides the magnitude in place, returning the remainder
+    fn div_small(&mut self, divisor: u32) -> u32 {
+        let mut rem = 0u64;
+        for d in self.digits.iter_mut().rev() {
+            let t = (rem << 32) | *d as u64;
+            *d = (t / divisor as u64) as u32;
+            rem = t % divisor as u64;
+        }
+        rem as u32
+    }
+
+    fn mul_add_small(&mut self, factor: u32, addend: u32) {
+        let mut carry = addend as u64;
+        for d in self.digits.iter_mut() {
+            let t = *d as u64 * factor as u64 + carry;
+            *d = t as u32;
+            carry = t >> 32;
+        }
+        if carry != 0 {
+            self.digits.push(carry as u32);
+        }
+    }
+}
+
+fn cmp_digits(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_digits(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut res = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (i, &d) in long.iter().enumerate() {
+        let t = d as u64 + short.get(i).cloned().unwrap_or(0) as u64 + carry;
+        res.push(t as u32);
+        carry = t >> 32;
+    }
+    res.push(carry as u32);
+    res
+}
+
+// a - b, with a at least as large as b
+fn sub_digits(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut res = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &d) in a.iter().enumerate() {
+        let mut t = d as i64 - b.get(i).cloned().unwrap_or(0) as i64 - borrow;
+        borrow = 0;
+        if t < 0 {
+            t += 1 << 32;
+            borrow = 1;
+        }
+        res.push(t as u32);
+    }
+    res
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_digits(&self.digits, &other.digits),
+            (true, true) => cmp_digits(&other.digits, &self.digits),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // nine decimal digits at a time, least significant first
+        let mut rest = self.clone();
+        let mut chunks = Vec::new();
+        loop {
+            chunks.push(rest.div_small(1_000_000_000));
+            rest = rest.normalize();
+            if rest.is_zero() {
+                break;
+            }
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", chunks.pop().unwrap_or(0))?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ParseBigIntError;
+
+impl fmt::Display for ParseBigIntError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid integer")
+    }
+}
+
+impl Error for ParseBigIntError {}
+
+impl FromStr for BigInt {
+    type Err = ParseBigIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseBigIntError);
+        }
+        let mut res = BigInt::default();
+        for b in digits.bytes() {
+            res.mul_add_small(10, (b - b'0') as u32);
+        }
+        res.negative = negative;
+        Ok(res.normalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(s: &str) -> BigInt {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(big("2").add(&big("3")), big("5"));
+        assert_eq!(big("-2").add(&big("3")), big("1"));
+        assert_eq!(big("2").add(&big("-3")), big("-1"));
+        assert_eq!(big("-7").add(&big("7")), BigInt::default());
+        assert_eq!(big("-6").mul(&big("7")), big("-42"));
+        assert_eq!(big("-6").mul(&big("-7")), big("42"));
+        assert_eq!(big("-6").mul(&big("0")), BigInt::default());
+        // carries and borrows across digits
+        assert_eq!(big("4294967295").add(&big("1")), big("4294967296"));
+        assert_eq!(big("4294967296").add(&big("-1")), big("4294967295"));
+        assert_eq!(big("18446744073709551616").mul(&big("18446744073709551616")), big("340282366920938463463374607431768211456"));
+    }
+
+    #[test]
+    fn factorial() {
+        let product = (1..=30).fold(BigInt::from_i128(1), |acc, i| acc.mul(&BigInt::from_i128(i)));
+        assert_eq!(product.to_string(), "265252859812191058636308480000000");
+        assert_eq!(product.to_i128(), Some(265252859812191058636308480000000));
+    }
+
+    #[test]
+    fn text() {
+        for s in &["0", "1", "-1", "999999999", "1000000000", "-1000000000000000001", "340282366920938463463374607431768211456"] {
+            assert_eq!(big(s).to_string(), *s);
+        }
+        assert_eq!(big("+12").to_string(), "12");
+        assert_eq!(big("-0").to_string(), "0");
+        assert_eq!(big("007").to_string(), "7");
+        for s in &["", "-", "+", "1-2", "12a", " 1", "--1"] {
+            assert_eq!(s.parse::<BigInt>(), Err(ParseBigIntError), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn i128_range() {
+        for &v in &[0, 1, -1, i64::MIN as i128, i128::MAX, i128::MIN, i128::MIN + 1] {
+            assert_eq!(BigInt::from_i128(v).to_i128(), Some(v));
+            assert_eq!(BigInt::from_i128(v).to_string(), v.to_string());
+        }
+        let one = BigInt::from_i128(1);
+        assert_eq!(BigInt::from_i128(i128::MAX).add(&one).to_i128(), None);
+        assert_eq!(BigInt::from_i128(i128::MIN).add(&one.mul(&BigInt::from_i128(-1))).to_i128(), None);
+    }
+}
@@ -0,0 +1,278 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+
+use crate::bigint::BigInt;
+use crate::cell::Cell;
+
+// layout, all integers little endian:
+//   magic "ICB\x1a", version, cell width in bytes (0 for unbounded), flags
+//   number of cells as an unsigned varint
+//   every cell as a zigzag varint: 0, -1, 1, -2, 2... as 0, 1, 2, 3, 4...
+//   crc32 of everything before it, if the checksum flag is set
+pub const MAGIC: &[u8] = b"ICB\x1a";
+pub const VERSION: u8 = 1;
+const FLAG_CHECKSUM: u8 = 1;
+
+// what is wrong with a binary program
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FormatError(pub String);
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for FormatError {}
+
+pub struct Header {
+    pub width: u8,
+    pub checksum: bool,
+}
+
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub fn write_cells<C: Cell>(code: &[C], checksum: bool) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION);
+    bytes.push(C::width());
+    bytes.push(if checksum { FLAG_CHECKSUM } else { 0 });
+    write_varint(&mut bytes, &(code.len() as i128).to_digits().1);
+    for cell in code {
+        let (negative, digits) = cell.to_digits();
+        write_varint(&mut bytes, &zigzag(negative, digits));
+    }
+    if checksum {
+        let crc = crc32(&bytes);
+        bytes.extend_from_slice(&crc.to_le_bytes());
+    }
+    bytes
+}
+
+pub fn read_header(bytes: &[u8]) -> Result<Header, FormatError> {
+    if !is_binary(bytes) {
+        return Err(FormatError("not a binary program".to_string()));
+    }
+    match bytes.get(MAGIC.len()..MAGIC.len() + 3) {
+        Some(&[version, width, flags]) => {
+            if version != VERSION {
+                return Err(FormatError(format!("unsupported version {}", version)));
+            }
+            if flags & !FLAG_CHECKSUM != 0 {
+                return Err(FormatError(format!("unknown flags {:#x}", flags)));
+            }
+            Ok(Header {
+                width,
+                checksum: flags & FLAG_CHECKSUM != 0,
+            })
+        }
+        _ => Err(FormatError("truncated header".to_string())),
+    }
+}
+
+// cells that don't fit the type are an error, a wider file loads fine as
+// long as its values are small enough
+pub fn read_cells<C: Cell>(bytes: &[u8]) -> Result<Vec<C>, FormatError> {
+    let header = read_header(bytes)?;
+    let mut body = &bytes[MAGIC.len() + 3..];
+    if header.checksum {
+        if body.len() < 4 {
+            return Err(FormatError("truncated checksum".to_string()));
+        }
+        let (rest, stored) = body.split_at(body.len() - 4);
+        let stored = u32::from_le_bytes([stored[0], stored[1], stored[2], stored[3]]);
+        let crc = crc32(&bytes[..bytes.len() - 4]);
+        if crc != stored {
+            return Err(FormatError(format!("checksum mismatch, stored {:08x}, computed {:08x}", stored, crc)));
+        }
+        body = rest;
+    }
+
+    let mut pos = 0;
+    let count = read_varint(body, &mut pos)?;
+    let count = BigInt::from_digits(false, count)
+        .to_i128()
+        .filter(|&c| c <= body.len() as i128)
+        .ok_or_else(|| FormatError("bad cell count".to_string()))? as usize;
+    let mut code = Vec::with_capacity(count);
+    for n in 0..count {
+        let (negative, digits) = unzigzag(read_varint(body, &mut pos)?);
+        let cell = C::from_digits(negative, digits).ok_or_else(|| FormatError(format!("cell {} doesn't fit", n)))?;
+        code.push(cell);
+    }
+    if pos != body.len() {
+        return Err(FormatError(format!("{} bytes after the last cell", body.len() - pos)));
+    }
+    Ok(code)
+}
+
+// 7 bits per byte, least significant first, high bit set on all but the last
+fn write_varint(bytes: &mut Vec<u8>, digits: &[u32]) {
+    let bits = match digits.last() {
+        Some(top) => digits.len() * 32 - top.leading_zeros() as usize,
+        None => 0,
+    };
+    let groups = bits.div_ceil(7).max(1);
+    for g in 0..groups {
+        let mut byte = 0u8;
+        for i in 0..7 {
+            let bit = g * 7 + i;
+            if bit < bits && digits[bit / 32] & (1 << (bit % 32)) != 0 {
+                byte |= 1 << i;
+            }
+        }
+        bytes.push(if g + 1 < groups { byte | 0x80 } else { byte });
+    }
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> Result<Vec<u32>, FormatError> {
+    let mut digits = Vec::new();
+    let mut bit = 0;
+    loop {
+        let byte = *bytes.get(*pos).ok_or_else(|| FormatError(format!("truncated at byte {}", *pos)))?;
+        *pos += 1;
+        for i in 0..7 {
+            if byte & (1 << i) != 0 {
+                let b = bit + i;
+                if digits.len() <= b / 32 {
+                    digits.resize(b / 32 + 1, 0);
+                }
+                digits[b / 32] |= 1 << (b % 32);
+            }
+        }
+        bit += 7;
+        if byte & 0x80 == 0 {
+            return Ok(digits);
+        }
+    }
+}
+
+// twice the magnitude, minus one if negative
+fn zigzag(negative: bool, mut digits: Vec<u32>) -> Vec<u32> {
+    let mut carry = 0;
+    for d in digits.iter_mut() {
+        let next = *d >> 31;
+        *d = (*d << 1) | carry;
+        carry = next;
+    }
+    if carry != 0 {
+        digits.push(carry);
+    }
+    if negative {
+        // the magnitude is never zero, so this doesn't borrow past the top
+        for d in digits.iter_mut() {
+            let (value, borrow) = d.overflowing_sub(1);
+            *d = value;
+            if !borrow {
+                break;
+            }
+        }
+    }
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+    digits
+}
+
+fn unzigzag(mut digits: Vec<u32>) -> (bool, Vec<u32>) {
+    let negative = digits.first().is_some_and(|d| d & 1 != 0);
+    let mut carry = 0;
+    for d in digits.iter_mut().rev() {
+        let next = *d & 1;
+        *d = (*d >> 1) | (carry << 31);
+        carry = next;
+    }
+    if negative {
+        for d in digits.iter_mut() {
+            let (value, overflow) = d.overflowing_add(1);
+            *d = value;
+            if !overflow {
+                break;
+            }
+        }
+        if digits.iter().all(|&d| d == 0) {
+            digits.push(1);
+        }
+    }
+    (negative, digits)
+}
+
+// the usual crc32 of zip and png
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let extremes = vec![0, 1, -1, 63, -64, 64, i64::MAX, i64::MIN, i64::MIN + 1];
+        for &checksum in &[true, false] {
+            let bytes = write_cells(&extremes, checksum);
+            assert_eq!(read_header(&bytes).map(|h| (h.width, h.checksum)), Ok((8, checksum)));
+            assert_eq!(read_cells::<i64>(&bytes), Ok(extremes.clone()));
+            assert_eq!(read_cells::<i128>(&bytes), Ok(extremes.iter().map(|&c| c as i128).collect()));
+        }
+        assert_eq!(read_cells::<i64>(&write_cells::<i64>(&[], true)), Ok(vec![]));
+
+        let big = ["0", "-1", "170141183460469231731687303715884105728", "-340282366920938463463374607431768211457"]
+            .iter()
+            .map(|s| s.parse::<BigInt>().unwrap())
+            .collect::<Vec<BigInt>>();
+        let bytes = write_cells(&big, true);
+        assert_eq!(read_header(&bytes).map(|h| h.width), Ok(0));
+        assert_eq!(read_cells::<BigInt>(&bytes), Ok(big));
+        assert_eq!(read_cells::<i128>(&bytes), Err(FormatError("cell 2 doesn't fit".to_string())));
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn rejects() {
+        let bytes = write_cells(&[1i64, 2, 3], true);
+        let error = |bytes: &[u8]| read_cells::<i64>(bytes).unwrap_err().0;
+
+        let mut flipped = bytes.clone();
+        flipped[8] ^= 1;
+        assert!(error(&flipped).starts_with("checksum mismatch"));
+        assert_eq!(error(&bytes[..MAGIC.len() + 2]), "truncated header");
+        assert_eq!(error(&bytes[..MAGIC.len() + 5]), "truncated checksum");
+        assert_eq!(error(b"ICC\x1a\x01\x08\x00\x00"), "not a binary program");
+
+        // without a checksum the damage shows in the structure
+        let bytes = write_cells(&[1i64, 2, 3], false);
+        assert_eq!(error(&bytes[..bytes.len() - 1]), "truncated at byte 3");
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert_eq!(error(&extra), "1 bytes after the last cell");
+        // more cells than bytes left
+        let mut count = bytes[..MAGIC.len() + 3].to_vec();
+        count.extend(&[0xff, 0x7f, 2, 4]);
+        assert_eq!(error(&count), "bad cell count");
+        let mut version = bytes.clone();
+        version[MAGIC.len()] = 2;
+        assert_eq!(error(&version), "unsupported version 2");
+        let mut flags = bytes;
+        flags[MAGIC.len() + 2] = 6;
+        assert_eq!(error(&flags), "unknown flags 0x6");
+    }
+}
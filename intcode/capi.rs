@@ -0,0 +1,379 @@
+// the C interface of libintcode. capi/intcode.h is generated from this
+// file by the header test below, so the comments above the functions and
+// constants are the header's documentation. cells are int64_t, and every
+// function accepts a null machine and does nothing with it. the functions
+// have doc comments since their safety sections go in the header too
+
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::slice;
+use std::str;
+
+use crate::binary;
+use crate::parse::parse_cells;
+use crate::vm::*;
+
+// bumped when a signature or the meaning of a return value changes
+pub const ABI_VERSION: u32 = 1;
+
+// what intcode_run stopped for, and intcode_status
+pub const INTCODE_READY: c_int = 0;
+pub const INTCODE_OUTPUT: c_int = 1;
+pub const INTCODE_NEEDS_INPUT: c_int = 2;
+pub const INTCODE_HALTED: c_int = 3;
+pub const INTCODE_ERROR: c_int = 4;
+pub const INTCODE_STEP_LIMIT: c_int = 5;
+
+#[derive(Clone)]
+pub struct Machine {
+    program: IntCodeProgram<i64>,
+    // the fault that stopped the machine, nul terminated for C
+    error: Option<CString>,
+}
+
+fn machine(code: Vec<i64>) -> *mut Machine {
+    Box::into_raw(Box::new(Machine {
+        program: IntCodeProgram::new(code),
+        error: None,
+    }))
+}
+
+#[no_mangle]
+pub extern "C" fn intcode_abi_version() -> u32 {
+    ABI_VERSION
+}
+
+/// a machine running a copy of len cells, null if len is 0
+///
+/// # Safety
+/// `code` is null or points to `len` readable cells.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_new(code: *const i64, len: usize) -> *mut Machine {
+    if code.is_null() || len == 0 {
+        return ptr::null_mut();
+    }
+    machine(slice::from_raw_parts(code, len).to_vec())
+}
+
+/// a machine from a program file's contents, text or binary. null if it
+/// doesn't parse
+///
+/// # Safety
+/// `buffer` is null or points to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_load(buffer: *const u8, len: usize) -> *mut Machine {
+    if buffer.is_null() {
+        return ptr::null_mut();
+    }
+    let bytes = slice::from_raw_parts(buffer, len);
+    let code = if binary::is_binary(bytes) {
+        binary::read_cells::<i64>(bytes).ok()
+    } else {
+        str::from_utf8(bytes).ok().and_then(|text| parse_cells::<i64>(text).ok())
+    };
+    code.map_or(ptr::null_mut(), machine)
+}
+
+/// an independent copy with the same memory, queues and status
+///
+/// # Safety
+/// `machine` is null or a machine from this library that isn't freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_snapshot(machine: *const Machine) -> *mut Machine {
+    match machine.as_ref() {
+        Some(machine) => Box::into_raw(Box::new(machine.clone())),
+        None => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+/// `machine` is null or a machine from this library that isn't freed yet.
+/// it can't be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_free(machine: *mut Machine) {
+    if !machine.is_null() {
+        drop(Box::from_raw(machine));
+    }
+}
+
+/// # Safety
+/// `machine` is null or a machine from this library that isn't freed yet.
+/// no other thread uses it at the same time.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_push_input(machine: *mut Machine, value: i64) {
+    if let Some(machine) = machine.as_mut() {
+        machine.program.add_input(value);
+    }
+}
+
+/// runs until the next output, missing input, halt or fault, or for at most
+/// max_steps instructions if that isn't 0
+///
+/// # Safety
+/// `machine` is null or a machine from this library that isn't freed yet.
+/// no other thread uses it at the same time.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_run(machine: *mut Machine, max_steps: u64) -> c_int {
+    let machine = match machine.as_mut() {
+        Some(machine) => machine,
+        None => return INTCODE_ERROR,
+    };
+    if machine.error.is_some() {
+        return INTCODE_ERROR;
+    }
+    let mut steps = 0;
+    loop {
+        if max_steps != 0 && steps == max_steps {
+            return INTCODE_STEP_LIMIT;
+        }
+        let outputs = machine.program.output.len();
+        match machine.program.step() {
+            Ok(State::Running) if machine.program.output.len() > outputs => return INTCODE_OUTPUT,
+            Ok(State::Running) => steps += 1,
+            Ok(State::WaitingForInput) => return INTCODE_NEEDS_INPUT,
+            Ok(State::Halted) => return INTCODE_HALTED,
+            Err(fault) => {
+                machine.error = CString::new(fault.to_string()).ok();
+                return INTCODE_ERROR;
+            }
+        }
+    }
+}
+
+/// 1 and the oldest output in *value if there is one, 0 otherwise. the
+/// output stays queued if value is null
+///
+/// # Safety
+/// `machine` is null or a machine from this library that isn't freed yet.
+/// `value` is null or points to a writable cell.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_pop_output(machine: *mut Machine, value: *mut i64) -> c_int {
+    let (machine, value) = match (machine.as_mut(), value.as_mut()) {
+        (Some(machine), Some(value)) => (machine, value),
+        _ => return 0,
+    };
+    match machine.program.take_output() {
+        Some(output) => {
+            *value = output;
+            1
+        }
+        None => 0,
+    }
+}
+
+/// INTCODE_READY if the machine can go on, or why it can't
+///
+/// # Safety
+/// `machine` is null or a machine from this library that isn't freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_status(machine: *const Machine) -> c_int {
+    let machine = match machine.as_ref() {
+        Some(machine) => machine,
+        None => return INTCODE_ERROR,
+    };
+    let opcode = machine.program.code.get(machine.program.ip).map(|op| op % 100);
+    if machine.error.is_some() {
+        INTCODE_ERROR
+    } else if machine.program.halted {
+        INTCODE_HALTED
+    } else if opcode == Some(OPCODE_INPUT as i64) && machine.program.input.is_empty() {
+        INTCODE_NEEDS_INPUT
+    } else {
+        INTCODE_READY
+    }
+}
+
+/// the fault message, valid until the machine is freed. null if there was
+/// no fault
+///
+/// # Safety
+/// `machine` is null or a machine from this library that isn't freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_error(machine: *const Machine) -> *const c_char {
+    match machine.as_ref().and_then(|m| m.error.as_ref()) {
+        Some(error) => error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// # Safety
+/// `machine` is null or a machine from this library that isn't freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_ip(machine: *const Machine) -> usize {
+    machine.as_ref().map_or(0, |m| m.program.ip)
+}
+
+/// copies up to len cells of memory into buffer and returns the memory size,
+/// call with a null buffer to get the size first
+///
+/// # Safety
+/// `machine` is null or a machine from this library that isn't freed yet.
+/// `buffer` is null or points to `len` writable cells.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_memory(machine: *const Machine, buffer: *mut i64, len: usize) -> usize {
+    let machine = match machine.as_ref() {
+        Some(machine) => machine,
+        None => return 0,
+    };
+    let code = &machine.program.code;
+    if !buffer.is_null() {
+        let n = len.min(code.len());
+        slice::from_raw_parts_mut(buffer, n).copy_from_slice(&code[..n]);
+    }
+    code.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    const PREAMBLE: &str = "/* libintcode, the Intcode machine for C. generated from capi.rs by its
+ * header test, don't edit; INTCODE_BLESS=1 rewrites it. cells are int64_t,
+ * and every function accepts a null machine and does nothing with it */
+#ifndef INTCODE_H
+#define INTCODE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern \"C\" {
+#endif
+
+typedef struct intcode_machine intcode_machine;
+";
+
+    const POSTAMBLE: &str = "#ifdef __cplusplus
+}
+#endif
+
+#endif
+";
+
+    fn dir() -> PathBuf {
+        Path::new(file!()).with_file_name("capi")
+    }
+
+    fn c_type(rust: &str) -> String {
+        let (pointer, target) = match (rust.strip_prefix("*const "), rust.strip_prefix("*mut ")) {
+            (Some(target), _) => ("const ", target),
+            (_, Some(target)) => ("", target),
+            _ => return c_scalar(rust).to_string(),
+        };
+        format!("{}{} *", pointer, c_scalar(target))
+    }
+
+    fn c_scalar(rust: &str) -> &str {
+        match rust {
+            "Machine" => "intcode_machine",
+            "i64" => "int64_t",
+            "u8" => "uint8_t",
+            "u32" => "uint32_t",
+            "u64" => "uint64_t",
+            "usize" => "size_t",
+            "c_int" => "int",
+            "c_char" => "char",
+            _ => panic!("no C type for {}", rust),
+        }
+    }
+
+    // `int64_t x` or `int64_t *x`
+    fn declare(rust: &str, name: &str) -> String {
+        let c = c_type(rust);
+        if c.ends_with('*') {
+            format!("{}{}", c, name)
+        } else {
+            format!("{} {}", c, name)
+        }
+    }
+
+    fn c_comment(lines: &[&str]) -> String {
+        let mut out = String::new();
+        for (i, line) in lines.iter().enumerate() {
+            out.push_str(if i == 0 { "/*" } else { " *" });
+            if !line.is_empty() {
+                out.push(' ');
+                out.push_str(line);
+            }
+            out.push_str(if i + 1 == lines.len() { " */\n" } else { "\n" });
+        }
+        out
+    }
+
+    // the declarations of every exported function and constant, with the
+    // comments above them
+    fn header(source: &str) -> String {
+        let mut out = PREAMBLE.to_string();
+        let mut comment = Vec::new();
+        let mut defines = false;
+        for line in source.lines() {
+            if let Some(text) = line.strip_prefix("// ").or_else(|| line.strip_prefix("///").map(str::trim_start)) {
+                comment.push(text);
+                continue;
+            }
+            if line.starts_with("#[") {
+                continue;
+            }
+            let define = line
+                .strip_prefix("pub const ")
+                .and_then(|rest| rest.strip_suffix(';'))
+                .and_then(|rest| rest.split_once(": "))
+                .and_then(|(name, rest)| Some((name, rest.split_once(" = ")?.1)));
+            if let Some((name, value)) = define {
+                if !defines || !comment.is_empty() {
+                    out.push('\n');
+                }
+                out.push_str(&c_comment(&comment));
+                let name = if name.starts_with("INTCODE_") { name.to_string() } else { format!("INTCODE_{}", name) };
+                out.push_str(&format!("#define {} {}\n", name, value));
+                defines = true;
+            } else if let Some(rest) = line.split_once("extern \"C\" fn ").map(|(_, rest)| rest) {
+                let (name, rest) = rest.split_once('(').unwrap();
+                let (params, rest) = rest.split_once(')').unwrap();
+                let returns = rest.trim().trim_end_matches('{').trim().strip_prefix("-> ").map_or("void".to_string(), c_type);
+                let params = params
+                    .split(", ")
+                    .filter(|p| !p.is_empty())
+                    .map(|p| {
+                        let (name, rust) = p.split_once(": ").unwrap();
+                        declare(rust, name)
+                    })
+                    .collect::<Vec<String>>();
+                let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+                out.push('\n');
+                out.push_str(&c_comment(&comment));
+                let returns = if returns.ends_with('*') { returns } else { returns + " " };
+                out.push_str(&format!("{}{}({});\n", returns, name, params));
+                defines = false;
+            } else {
+                defines = defines && line.is_empty();
+            }
+            comment.clear();
+        }
+        out.push('\n');
+        out + POSTAMBLE
+    }
+
+    #[test]
+    fn header_is_current() {
+        let header = header(include_str!("capi.rs"));
+        let path = dir().join("intcode.h");
+        if env::var_os("INTCODE_BLESS").is_some() {
+            fs::write(&path, &header).unwrap();
+        }
+        assert!(fs::read_to_string(&path).unwrap() == header, "{} is out of date, rerun with INTCODE_BLESS=1", path.display());
+    }
+
+    // builds the library and the C test program and runs it
+    #[test]
+    fn c_program() {
+        let output = Command::new("sh").arg(dir().join("test.sh")).output().unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}{}", stdout, String::from_utf8_lossy(&output.stderr));
+        assert!(stdout.contains("all checks passed"), "{}", stdout);
+    }
+}
@@ -0,0 +1,103 @@
+/* libintcode, the Intcode machine for C. generated from capi.rs by its
+ * header test, don't edit; INTCODE_BLESS=1 rewrites it. cells are int64_t,
+ * and every function accepts a null machine and does nothing with it */
+#ifndef INTCODE_H
+#define INTCODE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct intcode_machine intcode_machine;
+
+/* bumped when a signature or the meaning of a return value changes */
+#define INTCODE_ABI_VERSION 1
+
+/* what intcode_run stopped for, and intcode_status */
+#define INTCODE_READY 0
+#define INTCODE_OUTPUT 1
+#define INTCODE_NEEDS_INPUT 2
+#define INTCODE_HALTED 3
+#define INTCODE_ERROR 4
+#define INTCODE_STEP_LIMIT 5
+
+uint32_t intcode_abi_version(void);
+
+/* a machine running a copy of len cells, null if len is 0
+ *
+ * # Safety
+ * `code` is null or points to `len` readable cells. */
+intcode_machine *intcode_new(const int64_t *code, size_t len);
+
+/* a machine from a program file's contents, text or binary. null if it
+ * doesn't parse
+ *
+ * # Safety
+ * `buffer` is null or points to `len` readable bytes. */
+intcode_machine *intcode_load(const uint8_t *buffer, size_t len);
+
+/* an independent copy with the same memory, queues and status
+ *
+ * # Safety
+ * `machine` is null or a machine from this library that isn't freed yet. */
+intcode_machine *intcode_snapshot(const intcode_machine *machine);
+
+/* # Safety
+ * `machine` is null or a machine from this library that isn't freed yet.
+ * it can't be used afterwards. */
+void intcode_free(intcode_machine *machine);
+
+/* # Safety
+ * `machine` is null or a machine from this library that isn't freed yet.
+ * no other thread uses it at the same time. */
+void intcode_push_input(intcode_machine *machine, int64_t value);
+
+/* runs until the next output, missing input, halt or fault, or for at most
+ * max_steps instructions if that isn't 0
+ *
+ * # Safety
+ * `machine` is null or a machine from this library that isn't freed yet.
+ * no other thread uses it at the same time. */
+int intcode_run(intcode_machine *machine, uint64_t max_steps);
+
+/* 1 and the oldest output in *value if there is one, 0 otherwise. the
+ * output stays queued if value is null
+ *
+ * # Safety
+ * `machine` is null or a machine from this library that isn't freed yet.
+ * `value` is null or points to a writable cell. */
+int intcode_pop_output(intcode_machine *machine, int64_t *value);
+
+/* INTCODE_READY if the machine can go on, or why it can't
+ *
+ * # Safety
+ * `machine` is null or a machine from this library that isn't freed yet. */
+int intcode_status(const intcode_machine *machine);
+
+/* the fault message, valid until the machine is freed. null if there was
+ * no fault
+ *
+ * # Safety
+ * `machine` is null or a machine from this library that isn't freed yet. */
+const char *intcode_error(const intcode_machine *machine);
+
+/* # Safety
+ * `machine` is null or a machine from this library that isn't freed yet. */
+size_t intcode_ip(const intcode_machine *machine);
+
+/* copies up to len cells of memory into buffer and returns the memory size,
+ * call with a null buffer to get the size first
+ *
+ * # Safety
+ * `machine` is null or a machine from this library that isn't freed yet.
+ * `buffer` is null or points to `len` writable cells. */
+size_t intcode_memory(const intcode_machine *machine, int64_t *buffer, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,90 @@
+/* exercises the C interface, run by capi/test.sh */
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "intcode.h"
+
+static int failures = 0;
+
+#define CHECK(cond)                                                     \
+    do {                                                                \
+        if (!(cond)) {                                                  \
+            fprintf(stderr, "%s:%d: failed: %s\n", __FILE__, __LINE__, #cond); \
+            failures++;                                                 \
+        }                                                               \
+    } while (0)
+
+/* day5's larger example: 999 below 8, 1000 for 8, 1001 above */
+static const char *COMPARE =
+    "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,\n"
+    "1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,\n"
+    "999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99\n";
+
+static int64_t compare(const intcode_machine *original, int64_t input) {
+    intcode_machine *m = intcode_snapshot(original);
+    int64_t value = -1;
+    CHECK(intcode_status(m) == INTCODE_NEEDS_INPUT);
+    CHECK(intcode_run(m, 0) == INTCODE_NEEDS_INPUT);
+    intcode_push_input(m, input);
+    CHECK(intcode_run(m, 0) == INTCODE_OUTPUT);
+    /* nowhere to put it, so it stays queued */
+    CHECK(intcode_pop_output(m, NULL) == 0);
+    CHECK(intcode_pop_output(m, &value) == 1);
+    CHECK(intcode_pop_output(m, &value) == 0);
+    CHECK(intcode_run(m, 0) == INTCODE_HALTED);
+    CHECK(intcode_status(m) == INTCODE_HALTED);
+    intcode_free(m);
+    return value;
+}
+
+int main(void) {
+    CHECK(intcode_abi_version() == INTCODE_ABI_VERSION);
+
+    /* day2's example, memory after halting */
+    int64_t day2[] = {1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50};
+    intcode_machine *m = intcode_new(day2, sizeof(day2) / sizeof(day2[0]));
+    CHECK(m != NULL);
+    CHECK(intcode_run(m, 1) == INTCODE_STEP_LIMIT);
+    CHECK(intcode_ip(m) == 4);
+    CHECK(intcode_run(m, 0) == INTCODE_HALTED);
+    size_t len = intcode_memory(m, NULL, 0);
+    CHECK(len == 12);
+    int64_t *memory = calloc(len, sizeof(int64_t));
+    CHECK(intcode_memory(m, memory, len) == len);
+    CHECK(memory[0] == 3500 && memory[3] == 70);
+    free(memory);
+    CHECK(intcode_error(m) == NULL);
+    intcode_free(m);
+
+    /* a snapshot per input, the original stays untouched */
+    m = intcode_load((const uint8_t *)COMPARE, strlen(COMPARE));
+    CHECK(m != NULL);
+    CHECK(compare(m, 7) == 999);
+    CHECK(compare(m, 8) == 1000);
+    CHECK(compare(m, 9) == 1001);
+    intcode_free(m);
+
+    /* faults stick */
+    int64_t bad[] = {1, 100, 0, 0, 99};
+    m = intcode_new(bad, 5);
+    CHECK(intcode_run(m, 0) == INTCODE_ERROR);
+    CHECK(intcode_status(m) == INTCODE_ERROR);
+    CHECK(intcode_error(m) != NULL && strstr(intcode_error(m), "out of bounds") != NULL);
+    CHECK(intcode_run(m, 0) == INTCODE_ERROR);
+    intcode_free(m);
+
+    /* bad programs and null machines */
+    CHECK(intcode_load((const uint8_t *)"1,,2", 4) == NULL);
+    CHECK(intcode_new(NULL, 0) == NULL);
+    CHECK(intcode_run(NULL, 0) == INTCODE_ERROR);
+    intcode_push_input(NULL, 1);
+    intcode_free(NULL);
+
+    if (failures) {
+        fprintf(stderr, "%d checks failed\n", failures);
+        return 1;
+    }
+    printf("all checks passed\n");
+    return 0;
+}
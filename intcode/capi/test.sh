@@ -0,0 +1,13 @@
+#!/bin/sh
+# builds libintcode and runs the C test program against it, the c_program
+# test in capi.rs runs this. the no_std build of the library is checked
+# here too, nothing else builds it
+set -e
+cd "$(dirname "$0")/.."
+out=${TMPDIR:-/tmp}/intcode-capi
+mkdir -p "$out"
+
+rustc --edition 2018 --crate-type rlib --crate-name intcode --cfg 'feature="no_std"' lib.rs -o "$out/libintcode_no_std.rlib"
+rustc --edition 2018 -O --crate-type cdylib --crate-name intcode lib.rs -o "$out/libintcode.so"
+cc -Wall -Wextra -std=c99 -Icapi capi/test.c -L"$out" -lintcode -o "$out/test"
+LD_LIBRARY_PATH="$out" "$out/test"
@@ -0,0 +1,152 @@
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::bigint::BigInt;
+
+// a memory cell of the machine. opcodes, modes and addresses always fit in
+// an isize, values can be anything the cell type can hold
+pub trait Cell: Clone + PartialEq + PartialOrd + fmt::Display + fmt::Debug + FromStr {
+    fn from_isize(value: isize) -> Self;
+    // None if the value doesn't fit
+    fn to_isize(&self) -> Option<isize>;
+    // None on overflow
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn wrapping_add(&self, other: &Self) -> Self;
+    fn wrapping_mul(&self, other: &Self) -> Self;
+    // sign and magnitude in base 2^32, least significant digit first, for
+    // the binary program format. None if the value doesn't fit
+    fn to_digits(&self) -> (bool, Vec<u32>);
+    fn from_digits(negative: bool, digits: Vec<u32>) -> Option<Self>;
+    // size in bytes, 0 if unbounded
+    fn width() -> u8;
+}
+
+macro_rules! primitive_cell {
+    ($t:ty) => {
+        impl Cell for $t {
+            fn from_isize(value: isize) -> Self {
+                value as $t
+            }
+
+            fn to_isize(&self) -> Option<isize> {
+                let value = *self as isize;
+                if value as $t == *self {
+                    Some(value)
+                } else {
+                    None
+                }
+            }
+
+            fn checked_add(&self, other: &Self) -> Option<Self> {
+                <$t>::checked_add(*self, *other)
+            }
+
+            fn checked_mul(&self, other: &Self) -> Option<Self> {
+                <$t>::checked_mul(*self, *other)
+            }
+
+            fn wrapping_add(&self, other: &Self) -> Self {
+                <$t>::wrapping_add(*self, *other)
+            }
+
+            fn wrapping_mul(&self, other: &Self) -> Self {
+                <$t>::wrapping_mul(*self, *other)
+            }
+
+            fn to_digits(&self) -> (bool, Vec<u32>) {
+                let value = BigInt::from_i128(*self as i128);
+                let (negative, digits) = value.digits();
+                (negative, digits.to_vec())
+            }
+
+            fn from_digits(negative: bool, digits: Vec<u32>) -> Option<Self> {
+                let value = BigInt::from_digits(negative, digits).to_i128()?;
+                Some(value as $t).filter(|&v| v as i128 == value)
+            }
+
+            fn width() -> u8 {
+                core::mem::size_of::<$t>() as u8
+            }
+        }
+    };
+}
+
+primitive_cell!(isize);
+primitive_cell!(i64);
+primitive_cell!(i128);
+
+// never overflows, so checked and wrapping are the same
+impl Cell for BigInt {
+    fn from_isize(value: isize) -> Self {
+        BigInt::from_i128(value as i128)
+    }
+
+    fn to_isize(&self) -> Option<isize> {
+        self.to_i128().and_then(|v| v.to_isize())
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self.add(other))
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self.mul(other))
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self {
+        self.add(other)
+    }
+
+    fn wrapping_mul(&self, other: &Self) -> Self {
+        self.mul(other)
+    }
+
+    fn to_digits(&self) -> (bool, Vec<u32>) {
+        let (negative, digits) = self.digits();
+        (negative, digits.to_vec())
+    }
+
+    fn from_digits(negative: bool, digits: Vec<u32>) -> Option<Self> {
+        Some(BigInt::from_digits(negative, digits))
+    }
+
+    fn width() -> u8 {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overflow() {
+        assert_eq!(Cell::checked_add(&i64::MAX, &1), None);
+        assert_eq!(Cell::wrapping_add(&i64::MAX, &1), i64::MIN);
+        assert_eq!(Cell::checked_mul(&i128::MIN, &-1), None);
+        assert_eq!(Cell::wrapping_mul(&i128::MIN, &-1), i128::MIN);
+        assert_eq!(Cell::checked_mul(&-3isize, &4), Some(-12));
+
+        let max = BigInt::from_i128(i128::MAX);
+        let sum = Cell::checked_add(&max, &max).unwrap();
+        assert_eq!(sum, Cell::wrapping_add(&max, &max));
+        assert_eq!(sum.to_string(), "340282366920938463463374607431768211454");
+        assert_eq!(sum.to_isize(), None);
+    }
+
+    #[test]
+    fn digits() {
+        for &v in &[0i128, 5, -5, i64::MAX as i128, i64::MIN as i128, i128::MAX, i128::MIN] {
+            let (negative, digits) = v.to_digits();
+            assert_eq!(<i128 as Cell>::from_digits(negative, digits.clone()), Some(v));
+            assert_eq!(<BigInt as Cell>::from_digits(negative, digits).unwrap().to_i128(), Some(v));
+        }
+        // too wide for a narrower cell
+        let (negative, digits) = (i64::MAX as i128 + 1).to_digits();
+        assert_eq!(<i64 as Cell>::from_digits(negative, digits), None);
+        assert_eq!(BigInt::from_isize(-7).to_isize(), Some(-7));
+        assert_eq!(i128::MAX.to_isize(), None);
+    }
+}
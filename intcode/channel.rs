@@ -0,0 +1,177 @@
+// unbounded queues between async machines. the sending end is an
+// AsyncOutput and the receiving end an AsyncInput; both can move between
+// threads, so the machines run on any executor
+use std::collections::VecDeque;
+use std::future::poll_fn;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+use crate::asyncio::*;
+
+struct Shared<C> {
+    queue: VecDeque<C>,
+    senders: usize,
+    // the receiver waiting for a value
+    waker: Option<Waker>,
+}
+
+fn lock<C>(shared: &Mutex<Shared<C>>) -> MutexGuard<'_, Shared<C>> {
+    shared.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub struct Sender<C> {
+    shared: Arc<Mutex<Shared<C>>>,
+}
+
+pub struct Receiver<C> {
+    shared: Arc<Mutex<Shared<C>>>,
+}
+
+// the receiver sees the end of the queue once every sender is dropped
+pub fn channel<C>() -> (Sender<C>, Receiver<C>) {
+    let shared = Arc::new(Mutex::new(Shared {
+        queue: VecDeque::new(),
+        senders: 1,
+        waker: None,
+    }));
+    (Sender { shared: shared.clone() }, Receiver { shared })
+}
+
+impl<C> Sender<C> {
+    // never waits, values sent after the receiver is gone are dropped
+    pub fn send(&self, value: C) {
+        let waker = {
+            let mut shared = lock(&self.shared);
+            shared.queue.push_back(value);
+            shared.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<C> Clone for Sender<C> {
+    fn clone(&self) -> Self {
+        lock(&self.shared).senders += 1;
+        Sender { shared: self.shared.clone() }
+    }
+}
+
+impl<C> Drop for Sender<C> {
+    fn drop(&mut self) {
+        let waker = {
+            let mut shared = lock(&self.shared);
+            shared.senders -= 1;
+            if shared.senders == 0 {
+                shared.waker.take()
+            } else {
+                None
+            }
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<C> Receiver<C> {
+    pub fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<C>> {
+        let mut shared = lock(&self.shared);
+        if let Some(value) = shared.queue.pop_front() {
+            Poll::Ready(Some(value))
+        } else if shared.senders == 0 {
+            Poll::Ready(None)
+        } else {
+            shared.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    // None once the queue is empty and every sender is gone
+    pub async fn recv(&mut self) -> Option<C> {
+        poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    // the next queued value without waiting for one
+    pub fn try_recv(&mut self) -> Option<C> {
+        lock(&self.shared).queue.pop_front()
+    }
+}
+
+impl<C> AsyncInput<C> for Receiver<C> {
+    fn poll_read(&mut self, cx: &mut Context) -> Poll<Option<C>> {
+        self.poll_recv(cx)
+    }
+}
+
+impl<C> AsyncOutput<C> for Sender<C> {
+    fn poll_ready(&mut self, _cx: &mut Context) -> Poll<()> {
+        Poll::Ready(())
+    }
+
+    fn write(&mut self, value: C) {
+        self.send(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::executor::Executor;
+    use crate::vm::*;
+
+    // day7 part 2: five amplifiers in a ring, each starting with its phase
+    // setting and the first one with a 0, until they all halt
+    fn feedback_loop(code: &IntCodeProgram<i64>, phases: &[i64]) -> Option<i64> {
+        let last = Cell::new(None);
+        let mut executor = Executor::new();
+        let (senders, receivers): (Vec<_>, Vec<_>) = phases.iter().map(|_| channel()).unzip();
+        for (sender, &phase) in senders.iter().zip(phases) {
+            sender.send(phase);
+        }
+        senders[0].send(0);
+
+        // the last amplifier's outputs go back to the first through here,
+        // keeping the last one as the thruster signal
+        let (output, mut thrusters) = channel();
+        let mut senders = senders.into_iter();
+        let first = senders.next().unwrap();
+        for (input, output) in receivers.into_iter().zip(senders.chain(Some(output))) {
+            let mut program = code.clone();
+            executor.spawn(async move {
+                assert_eq!(run_async(&mut program, input, output).await, Ok(State::Halted));
+            });
+        }
+        let last_ref = &last;
+        executor.spawn(async move {
+            while let Some(value) = thrusters.recv().await {
+                last_ref.set(Some(value));
+                first.send(value);
+            }
+        });
+        assert_eq!(executor.run(), 0);
+        drop(executor);
+        last.get()
+    }
+
+    #[test]
+    fn day7_feedback_loop() {
+        let code = include_str!("fuzz/corpus/day7-example4.txt").parse::<IntCodeProgram<i64>>().unwrap();
+        assert_eq!(feedback_loop(&code, &[9, 8, 7, 6, 5]), Some(139629729));
+    }
+
+    #[test]
+    fn waiting_machine_is_left() {
+        let (_sender, input) = channel::<i64>();
+        let (output, _receiver) = channel();
+        let mut program = "3,0,4,0,99".parse::<IntCodeProgram<i64>>().unwrap();
+        let mut executor = Executor::new();
+        executor.spawn(async move {
+            let _ = run_async(&mut program, input, output).await;
+        });
+        assert_eq!(executor.run(), 1);
+    }
+}
@@ -0,0 +1,520 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+
+use crate::decode::*;
+use crate::vm::*;
+
+#[derive(Debug)]
+pub struct CompileError {
+    pub line: usize,
+    pub col: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.col, self.msg)
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Num(isize),
+    Ident(String),
+    Sym(&'static str),
+    End,
+}
+
+const SYMBOLS: [&str; 17] = [
+    "==", "!=", "<=", ">=", "<", ">", "=", "+", "-", "*", "!", "(", ")", "{", "}", ";", ",",
+];
+
+fn tokenize(src: &str) -> Result<Vec<(Token, usize, usize)>, CompileError> {
+    let mut tokens = Vec::new();
+    for (i, line) in src.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut col = 0;
+        while col < line.len() {
+            let rest = &line[col..];
+            let c = rest.chars().next().unwrap();
+            let pos = (i + 1, col + 1);
+            if c.is_whitespace() {
+                col += c.len_utf8();
+            } else if c.is_ascii_digit() {
+                let len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+                let value = rest[..len].parse().map_err(|e| CompileError {
+                    line: pos.0,
+                    col: pos.1,
+                    msg: format!("bad number: {}", e),
+                })?;
+                tokens.push((Token::Num(value), pos.0, pos.1));
+                col += len;
+            } else if c.is_alphabetic() || c == '_' {
+                let len = rest.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(rest.len());
+                tokens.push((Token::Ident(rest[..len].to_string()), pos.0, pos.1));
+                col += len;
+            } else if let Some(sym) = SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+                tokens.push((Token::Sym(sym), pos.0, pos.1));
+                col += sym.len();
+            } else {
+                return Err(CompileError { line: pos.0, col: pos.1, msg: format!("unexpected character '{}'", c) });
+            }
+        }
+    }
+    let line = src.lines().count() + 1;
+    tokens.push((Token::End, line, 1));
+    Ok(tokens)
+}
+
+#[derive(Debug)]
+enum Expr {
+    Num(isize),
+    Var(String),
+    Input,
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug)]
+enum Stmt {
+    Assign(String, Expr),
+    Output(Expr),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn error<T>(&self, msg: String) -> Result<T, CompileError> {
+        let (_, line, col) = self.tokens[self.pos];
+        Err(CompileError { line, col, msg })
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].0.clone();
+        if token != Token::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn expect(&mut self, sym: &'static str) -> Result<(), CompileError> {
+        if *self.peek() == Token::Sym(sym) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.error(format!("expected '{}', found {:?}", sym, self.peek()))
+        }
+    }
+
+    fn accept(&mut self, sym: &'static str) -> bool {
+        if *self.peek() == Token::Sym(sym) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Token::Ident(s) if s == keyword)
+    }
+
+    fn program(&mut self) -> Result<Vec<Stmt>, CompileError> {
+        let mut stmts = Vec::new();
+        while *self.peek() != Token::End {
+            stmts.push(self.statement()?);
+        }
+        Ok(stmts)
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, CompileError> {
+        self.expect("{")?;
+        let mut stmts = Vec::new();
+        while !self.accept("}") {
+            if *self.peek() == Token::End {
+                return self.error("expected '}'".to_string());
+            }
+            stmts.push(self.statement()?);
+        }
+        Ok(stmts)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, CompileError> {
+        if self.is_keyword("if") {
+            self.next();
+            let cond = self.condition()?;
+            let then = self.block()?;
+            let mut els = Vec::new();
+            if self.is_keyword("else") {
+                self.next();
+                els = if self.is_keyword("if") { vec![self.statement()?] } else { self.block()? };
+            }
+            return Ok(Stmt::If(cond, then, els));
+        }
+        if self.is_keyword("while") {
+            self.next();
+            let cond = self.condition()?;
+            return Ok(Stmt::While(cond, self.block()?));
+        }
+        if self.is_keyword("output") {
+            self.next();
+            let e = self.condition()?;
+            self.expect(";")?;
+            return Ok(Stmt::Output(e));
+        }
+        match self.next() {
+            Token::Ident(name) if !is_reserved(&name) => {
+                self.expect("=")?;
+                let e = self.expression()?;
+                self.expect(";")?;
+                Ok(Stmt::Assign(name, e))
+            }
+            token => {
+                self.pos -= 1;
+                self.error(format!("expected statement, found {:?}", token))
+            }
+        }
+    }
+
+    fn condition(&mut self) -> Result<Expr, CompileError> {
+        self.expect("(")?;
+        let e = self.expression()?;
+        self.expect(")")?;
+        Ok(e)
+    }
+
+    fn expression(&mut self) -> Result<Expr, CompileError> {
+        let lhs = self.additive()?;
+        for &op in &["==", "!=", "<=", ">=", "<", ">"] {
+            if self.accept(op) {
+                let rhs = self.additive()?;
+                return Ok(Expr::Binary(op, Box::new(lhs), Box::new(rhs)));
+            }
+        }
+        Ok(lhs)
+    }
+
+    fn additive(&mut self) -> Result<Expr, CompileError> {
+        let mut lhs = self.term()?;
+        loop {
+            let op = if self.accept("+") {
+                "+"
+            } else if self.accept("-") {
+                "-"
+            } else {
+                return Ok(lhs);
+            };
+            let rhs = self.term()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, CompileError> {
+        let mut lhs = self.unary()?;
+        while self.accept("*") {
+            let rhs = self.unary()?;
+            lhs = Expr::Binary("*", Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, CompileError> {
+        if self.accept("-") {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        if self.accept("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.accept("(") {
+            let e = self.expression()?;
+            self.expect(")")?;
+            return Ok(e);
+        }
+        match self.next() {
+            Token::Num(n) => Ok(Expr::Num(n)),
+            Token::Ident(name) if name == "input" => {
+                self.expect("(")?;
+                self.expect(")")?;
+                Ok(Expr::Input)
+            }
+            Token::Ident(name) if !is_reserved(&name) => Ok(Expr::Var(name)),
+            token => {
+                self.pos -= 1;
+                self.error(format!("expected expression, found {:?}", token))
+            }
+        }
+    }
+}
+
+fn is_reserved(name: &str) -> bool {
+    ["if", "else", "while", "input", "output"].contains(&name)
+}
+
+// operands before the final layout is known
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Arg {
+    Imm(isize),
+    Var(usize),
+    Temp(usize),
+    Label(usize),
+}
+
+struct Generator {
+    instructions: Vec<(isize, Vec<Arg>)>,
+    labels: Vec<usize>, // instruction index of each label
+    vars: HashMap<String, usize>,
+    temps: usize,
+    max_temps: usize,
+}
+
+impl Generator {
+    fn emit(&mut self, opcode: isize, args: Vec<Arg>) {
+        self.instructions.push((opcode, args));
+    }
+
+    fn new_label(&mut self) -> usize {
+        self.labels.push(usize::MAX);
+        self.labels.len() - 1
+    }
+
+    fn place(&mut self, label: usize) {
+        self.labels[label] = self.instructions.len();
+    }
+
+    fn var(&mut self, name: &str) -> Arg {
+        let n = self.vars.len();
+        Arg::Var(*self.vars.entry(name.to_string()).or_insert(n))
+    }
+
+    fn temp(&mut self) -> Arg {
+        self.temps += 1;
+        self.max_temps = self.max_temps.max(self.temps);
+        Arg::Temp(self.temps - 1)
+    }
+
+    fn statements(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            // temporaries only live within a statement
+            self.temps = 0;
+            match stmt {
+                Stmt::Assign(name, e) => {
+                    let dest = self.var(name);
+                    self.expr_into(e, dest);
+                }
+                Stmt::Output(e) => {
+                    let a = self.expr(e);
+                    self.emit(OPCODE_OUTPUT, vec![a]);
+                }
+                Stmt::If(cond, then, els) => {
+                    let else_label = self.new_label();
+                    let c = self.expr(cond);
+                    self.emit(OPCODE_JUMP_IF_FALSE, vec![c, Arg::Label(else_label)]);
+                    self.statements(then);
+                    if els.is_empty() {
+                        self.place(else_label);
+                    } else {
+                        let end_label = self.new_label();
+                        self.emit(OPCODE_JUMP_IF_TRUE, vec![Arg::Imm(1), Arg::Label(end_label)]);
+                        self.place(else_label);
+                        self.statements(els);
+                        self.place(end_label);
+                    }
+                }
+                Stmt::While(cond, body) => {
+                    let start = self.new_label();
+                    let end = self.new_label();
+                    self.place(start);
+                    let c = self.expr(cond);
+                    self.emit(OPCODE_JUMP_IF_FALSE, vec![c, Arg::Label(end)]);
+                    self.statements(body);
+                    self.emit(OPCODE_JUMP_IF_TRUE, vec![Arg::Imm(1), Arg::Label(start)]);
+                    self.place(end);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, e: &Expr) -> Arg {
+        match e {
+            Expr::Num(n) => Arg::Imm(*n),
+            Expr::Var(name) => self.var(name),
+            _ => {
+                let t = self.temp();
+                self.expr_into(e, t);
+                t
+            }
+        }
+    }
+
+    fn expr_into(&mut self, e: &Expr, dest: Arg) {
+        match e {
+            Expr::Num(_) | Expr::Var(_) => {
+                let a = self.expr(e);
+                self.emit(OPCODE_ADD, vec![a, Arg::Imm(0), dest]);
+            }
+            Expr::Input => self.emit(OPCODE_INPUT, vec![dest]),
+            Expr::Neg(a) => {
+                let a = self.expr(a);
+                self.emit(OPCODE_MULT, vec![a, Arg::Imm(-1), dest]);
+            }
+            Expr::Not(a) => {
+                let a = self.expr(a);
+                self.emit(OPCODE_EQUALS, vec![a, Arg::Imm(0), dest]);
+            }
+            Expr::Binary(op, a, b) => {
+                let a = self.expr(a);
+                let b = self.expr(b);
+                match *op {
+                    "+" => self.emit(OPCODE_ADD, vec![a, b, dest]),
+                    "*" => self.emit(OPCODE_MULT, vec![a, b, dest]),
+                    "-" => match b {
+                        Arg::Imm(k) if k != isize::MIN => self.emit(OPCODE_ADD, vec![a, Arg::Imm(-k), dest]),
+                        _ => {
+                            let t = self.temp();
+                            self.emit(OPCODE_MULT, vec![b, Arg::Imm(-1), t]);
+                            self.emit(OPCODE_ADD, vec![a, t, dest]);
+                        }
+                    },
+                    "<" => self.emit(OPCODE_LESS_THAN, vec![a, b, dest]),
+                    ">" => self.emit(OPCODE_LESS_THAN, vec![b, a, dest]),
+                    "==" => self.emit(OPCODE_EQUALS, vec![a, b, dest]),
+                    // the negated forms go through a temporary
+                    _ => {
+                        let t = self.temp();
+                        match *op {
+                            "<=" => self.emit(OPCODE_LESS_THAN, vec![b, a, t]),
+                            ">=" => self.emit(OPCODE_LESS_THAN, vec![a, b, t]),
+                            _ => self.emit(OPCODE_EQUALS, vec![a, b, t]),
+                        }
+                        self.emit(OPCODE_EQUALS, vec![t, Arg::Imm(0), dest]);
+                    }
+                }
+            }
+        }
+    }
+
+    // code first, then a halt, then variables and temporaries
+    fn link(mut self) -> Vec<isize> {
+        self.emit(OPCODE_HALT, Vec::new());
+        let mut addrs = Vec::with_capacity(self.instructions.len() + 1);
+        let mut addr = 0;
+        for (_, args) in &self.instructions {
+            addrs.push(addr);
+            addr += 1 + args.len();
+        }
+        addrs.push(addr);
+        let vars_start = addr as isize;
+        let temps_start = vars_start + self.vars.len() as isize;
+
+        let mut code = Vec::new();
+        for (opcode, args) in &self.instructions {
+            let params = args
+                .iter()
+                .map(|arg| match *arg {
+                    Arg::Imm(v) => Param::Immediate(v),
+                    Arg::Label(l) => Param::Immediate(addrs[self.labels[l]] as isize),
+                    Arg::Var(v) => Param::Position(vars_start + v as isize),
+                    Arg::Temp(t) => Param::Position(temps_start + t as isize),
+                })
+                .collect();
+            code.extend(Instruction::new(code.len(), *opcode, params).encode());
+        }
+        code.resize(code.len() + self.vars.len() + self.max_temps, 0);
+        code
+    }
+}
+
+pub fn compile(src: &str) -> Result<Vec<isize>, CompileError> {
+    let mut parser = Parser { tokens: tokenize(src)?, pos: 0 };
+    let stmts = parser.program()?;
+    let mut gen = Generator {
+        instructions: Vec::new(),
+        labels: Vec::new(),
+        vars: HashMap::new(),
+        temps: 0,
+        max_temps: 0,
+    };
+    gen.statements(&stmts);
+    Ok(gen.link())
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut src = String::new();
+    match args.first() {
+        Some(filename) => File::open(filename)?.read_to_string(&mut src)?,
+        None => io::stdin().read_to_string(&mut src)?,
+    };
+    let code = compile(&src).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    println!("{}", format_code(&code));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+    use crate::ascii::encode_line;
+
+    // the values of a `# input:` or `# output:` line. a quoted string is a
+    // line of text, its ASCII codes and a newline
+    fn values(text: &str) -> Vec<isize> {
+        let mut values = Vec::new();
+        let mut rest = text.trim();
+        while !rest.is_empty() {
+            if let Some(quoted) = rest.strip_prefix('"') {
+                let end = quoted.find('"').expect("unterminated string");
+                values.extend(encode_line::<isize>(&quoted[..end]).unwrap());
+                rest = &quoted[end + 1..];
+            } else {
+                let end = rest.find(',').unwrap_or(rest.len());
+                values.push(rest[..end].trim().parse().expect("bad value"));
+                rest = &rest[end..];
+            }
+            rest = rest.trim_start().trim_start_matches(',').trim_start();
+        }
+        values
+    }
+
+    #[test]
+    fn examples() {
+        let dir = Path::new(file!()).with_file_name("examples");
+        let mut count = 0;
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|e| e != "ic") {
+                continue;
+            }
+            let src = fs::read_to_string(&path).unwrap();
+            let header = |name: &str| src.lines().find_map(|l| l.strip_prefix(name)).map(values);
+            let input = header("# input:").unwrap_or_default();
+            let expected = header("# output:").unwrap_or_else(|| panic!("{} has no output line", path.display()));
+
+            let code = compile(&src).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            let mut program = IntCodeProgram::new(code);
+            for value in input {
+                program.add_input(value);
+            }
+            assert_eq!(program.execute(), Ok(State::Halted), "{}", path.display());
+            assert_eq!(program.output, expected, "{}", path.display());
+            count += 1;
+        }
+        assert!(count > 0, "no examples in {}", dir.display());
+    }
+}
@@ -0,0 +1,55 @@
+// stdin and stdout as machine input and output, like the day solutions
+use std::fmt::Display;
+use std::io;
+use std::io::prelude::*;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use crate::ports::Output;
+
+// one value per line, blank lines are skipped. a line that isn't a value
+// is reported on stderr and skipped too
+pub struct StdinValues<C> {
+    cell: PhantomData<C>,
+}
+
+impl<C> StdinValues<C> {
+    pub fn new() -> Self {
+        StdinValues { cell: PhantomData }
+    }
+}
+
+impl<C> Default for StdinValues<C> {
+    fn default() -> Self {
+        StdinValues::new()
+    }
+}
+
+impl<C: FromStr> Iterator for StdinValues<C> {
+    type Item = C;
+
+    fn next(&mut self) -> Option<C> {
+        loop {
+            // so a prompt shows before waiting for the value
+            io::stdout().flush().ok()?;
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line).ok()? == 0 {
+                return None;
+            }
+            match line.trim().parse() {
+                Ok(value) => return Some(value),
+                Err(_) if line.trim().is_empty() => {}
+                Err(_) => eprintln!("not a value: {}", line.trim()),
+            }
+        }
+    }
+}
+
+// prints `output: N` lines
+pub struct StdoutValues;
+
+impl<C: Display> Output<C> for StdoutValues {
+    fn write(&mut self, value: C) {
+        println!("output: {}", value);
+    }
+}
@@ -0,0 +1,58 @@
+use std::error::Error;
+use std::fs;
+use std::io;
+
+use crate::bigint::BigInt;
+use crate::binary::*;
+use crate::cell::Cell;
+use crate::host::*;
+
+fn convert_cells<C: Cell>(input: &str, output: &str, checksum: bool) -> io::Result<()>
+where
+    C::Err: Error + Send + Sync + 'static,
+{
+    let program = load_cells::<C>(Some(input))?;
+    fs::write(output, write_cells(&program.code, checksum))
+}
+
+// text programs become binary and binary ones text
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut cells = "isize".to_string();
+    let mut checksum = true;
+    let mut files = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--cells" => cells = value(&mut iter, arg)?,
+            "--no-checksum" => checksum = false,
+            _ => files.push(arg.as_str()),
+        }
+    }
+    let (input, output) = match files.as_slice() {
+        [input, output] => (*input, *output),
+        _ => return Err(invalid("usage: intcode convert [--cells type] [--no-checksum] <input> <output>".to_string())),
+    };
+
+    let bytes = fs::read(input)?;
+    if is_binary(&bytes) {
+        let header = read_header(&bytes)?;
+        let text = match header.width {
+            0 => format_cells(&read_cells::<BigInt>(&bytes)?),
+            16 => format_cells(&read_cells::<i128>(&bytes)?),
+            _ => format_cells(&read_cells::<i64>(&bytes)?),
+        };
+        return fs::write(output, text + "\n");
+    }
+    match cells.as_str() {
+        "isize" => convert_cells::<isize>(input, output, checksum),
+        "i64" => convert_cells::<i64>(input, output, checksum),
+        "i128" => convert_cells::<i128>(input, output, checksum),
+        "big" => convert_cells::<BigInt>(input, output, checksum),
+        _ => Err(invalid(format!("unknown cell type {}, use isize, i64, i128 or big", cells))),
+    }
+}
+
+fn format_cells<C: Cell>(code: &[C]) -> String {
+    code.iter().map(|c| c.to_string()).collect::<Vec<String>>().join(",")
+}
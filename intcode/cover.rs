@@ -0,0 +1,73 @@
+// the coverage command
+use std::fs;
+use std::io;
+
+use crate::coverage::Coverage;
+use crate::host::*;
+use crate::vm::*;
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut runs = Vec::new();
+    let mut merge = Vec::new();
+    let mut save = None;
+    let mut max_steps = 1_000_000;
+    let mut filename = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            // one run per --run, with its input values: --run 1 --run 5
+            "--run" => {
+                let value = value(&mut iter, arg)?;
+                let parsed = value.split(',').map(|v| v.trim().parse::<isize>()).collect::<Result<Vec<isize>, _>>();
+                runs.push(parsed.map_err(|_| invalid(format!("bad inputs {}", value)))?);
+            }
+            // adds coverage saved by an earlier --save
+            "--merge" => merge.push(value(&mut iter, arg)?),
+            "--save" => save = Some(value(&mut iter, arg)?),
+            "--max-steps" => {
+                let value = value(&mut iter, arg)?;
+                max_steps = value.parse::<usize>().map_err(|_| invalid(format!("bad number {}", value)))?;
+            }
+            _ => filename = Some(arg.as_str()),
+        }
+    }
+    if runs.is_empty() && merge.is_empty() {
+        runs.push(Vec::new());
+    }
+
+    let program = load_program(filename)?;
+    let mut coverage = Coverage::new();
+    for filename in &merge {
+        let text = fs::read_to_string(filename)?;
+        let loaded = Coverage::from_text(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", filename, e)))?;
+        coverage.merge(&loaded);
+    }
+    for (i, inputs) in runs.iter().enumerate() {
+        let mut machine = program.clone();
+        machine.input = inputs.clone();
+        machine.coverage = Some(Coverage::new());
+        let mut steps = 0;
+        let status = loop {
+            if steps == max_steps {
+                break "step limit".to_string();
+            }
+            match machine.step() {
+                Ok(State::Running) => steps += 1,
+                Ok(State::Halted) => break "halted".to_string(),
+                Ok(State::WaitingForInput) => break "needs input".to_string(),
+                Err(fault) => break fault.to_string(),
+            }
+        };
+        let inputs = inputs.iter().map(|v| v.to_string()).collect::<Vec<String>>();
+        let inputs = if inputs.is_empty() { "no input".to_string() } else { format!("input {}", inputs.join(",")) };
+        println!("run {} with {}: {} after {} steps", i + 1, inputs, status, steps);
+        coverage.merge(machine.coverage.as_ref().unwrap());
+    }
+
+    print!("{}", coverage.report(&program.code));
+    if let Some(save) = save {
+        fs::write(save, coverage.to_text())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write as _;
+
+use crate::decode::*;
+
+// how often each address ran as an instruction, and for jumps how often
+// they were taken and not taken. runs of the same program add up
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Coverage {
+    pub hits: BTreeMap<usize, u64>,
+    pub branches: BTreeMap<usize, (u64, u64)>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage::default()
+    }
+
+    pub fn hit(&mut self, addr: usize) {
+        *self.hits.entry(addr).or_insert(0) += 1;
+    }
+
+    pub fn branch(&mut self, addr: usize, taken: bool) {
+        let counts = self.branches.entry(addr).or_insert((0, 0));
+        if taken {
+            counts.0 += 1;
+        } else {
+            counts.1 += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &Coverage) {
+        for (&addr, &count) in &other.hits {
+            *self.hits.entry(addr).or_insert(0) += count;
+        }
+        for (&addr, &(taken, not_taken)) in &other.branches {
+            let counts = self.branches.entry(addr).or_insert((0, 0));
+            counts.0 += taken;
+            counts.1 += not_taken;
+        }
+    }
+
+    // one line per address, what --save writes and --merge reads
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# intcode coverage\n");
+        for (addr, count) in &self.hits {
+            let _ = writeln!(text, "hit {} {}", addr, count);
+        }
+        for (addr, (taken, not_taken)) in &self.branches {
+            let _ = writeln!(text, "branch {} {} {}", addr, taken, not_taken);
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Coverage, String> {
+        let mut coverage = Coverage::new();
+        for (n, line) in text.lines().enumerate() {
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            let fields = line.split_whitespace().map(|f| f.parse::<u64>().ok()).collect::<Vec<Option<u64>>>();
+            match (line.split_whitespace().next(), fields.as_slice()) {
+                (Some("hit"), [_, Some(addr), Some(count)]) => {
+                    *coverage.hits.entry(*addr as usize).or_insert(0) += count;
+                }
+                (Some("branch"), [_, Some(addr), Some(taken), Some(not_taken)]) => {
+                    let counts = coverage.branches.entry(*addr as usize).or_insert((0, 0));
+                    counts.0 += taken;
+                    counts.1 += not_taken;
+                }
+                _ => return Err(format!("line {}: bad coverage line {}", n + 1, line)),
+            }
+        }
+        Ok(coverage)
+    }
+
+    // the disassembly with execution counts, ##### for instructions that
+    // never ran, then the totals
+    pub fn report(&self, code: &[isize]) -> String {
+        // everything that is statically reachable or did run, so code only
+        // reached through computed jumps is counted too
+        let flow = Flow::new(code);
+        let starts = flow.instructions.keys().chain(self.hits.keys()).cloned().collect::<BTreeSet<usize>>();
+
+        let mut out = String::new();
+        let mut branches = 0;
+        let mut covered_branches = 0;
+        let mut addr = 0;
+        while addr < code.len() {
+            let next_start = starts.range(addr + 1..).next().cloned().unwrap_or(code.len());
+            if !starts.contains(&addr) {
+                let data = code[addr..next_start].iter().map(|v| v.to_string()).collect::<Vec<String>>();
+                let _ = writeln!(out, "{:>10}  {:5}  data {}", "", addr, data.join(","));
+                addr = next_start;
+                continue;
+            }
+            let count = self.hits.get(&addr).map_or("#####".to_string(), |c| c.to_string());
+            match decode(code, addr) {
+                Some(instr) => {
+                    let _ = write!(out, "{:>10}  {:5}  {}", count, addr, instr);
+                    if instr.is_jump() {
+                        let (taken, not_taken) = self.branches.get(&addr).cloned().unwrap_or((0, 0));
+                        branches += 2;
+                        covered_branches += (taken > 0) as usize + (not_taken > 0) as usize;
+                        let partial = if taken == 0 || not_taken == 0 { "  partial" } else { "" };
+                        let _ = write!(out, "  taken {}, not taken {}{}", taken, not_taken, partial);
+                    }
+                    out.push('\n');
+                    addr = instr.next().min(next_start);
+                }
+                // ran as something else after the program changed it
+                None => {
+                    let _ = writeln!(out, "{:>10}  {:5}  {}", count, addr, code[addr]);
+                    addr += 1;
+                }
+            }
+        }
+
+        let executed = starts.iter().filter(|a| self.hits.contains_key(a)).count();
+        let percent = |n: usize, total: usize| if total == 0 { 100.0 } else { 100.0 * n as f64 / total as f64 };
+        let _ = writeln!(
+            out,
+            "instructions: {} of {} executed ({:.1}%)",
+            executed,
+            starts.len(),
+            percent(executed, starts.len())
+        );
+        let _ = writeln!(
+            out,
+            "branches: {} of {} directions taken ({:.1}%)",
+            covered_branches,
+            branches,
+            percent(covered_branches, branches)
+        );
+        out
+    }
+}
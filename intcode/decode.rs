@@ -0,0 +1,256 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::vm::*;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Param {
+    Position(isize),
+    Immediate(isize),
+}
+
+impl Param {
+    fn mode(self) -> isize {
+        match self {
+            Param::Position(_) => PMODE_POSITION,
+            Param::Immediate(_) => PMODE_IMMEDIATE,
+        }
+    }
+
+    pub fn value(self) -> isize {
+        match self {
+            Param::Position(v) | Param::Immediate(v) => v,
+        }
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Param::Position(p) => write!(f, "[{}]", p),
+            Param::Immediate(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Instruction {
+    pub addr: usize,
+    pub opcode: isize,
+    pub params: Vec<Param>,
+}
+
+// an instruction is never empty, there is always the opcode
+#[allow(clippy::len_without_is_empty)]
+impl Instruction {
+    pub fn new(addr: usize, opcode: isize, params: Vec<Param>) -> Self {
+        Instruction { addr, opcode, params }
+    }
+
+    pub fn len(&self) -> usize {
+        1 + self.params.len()
+    }
+
+    pub fn next(&self) -> usize {
+        self.addr + self.len()
+    }
+
+    pub fn is_jump(&self) -> bool {
+        self.opcode == OPCODE_JUMP_IF_TRUE || self.opcode == OPCODE_JUMP_IF_FALSE
+    }
+
+    // index of the parameter that holds the output address, if any
+    pub fn write_param(&self) -> Option<usize> {
+        match self.opcode {
+            OPCODE_ADD | OPCODE_MULT | OPCODE_LESS_THAN | OPCODE_EQUALS => Some(2),
+            OPCODE_INPUT => Some(0),
+            _ => None,
+        }
+    }
+
+    // the execute loop ignores the mode digit of output parameters
+    pub fn write_target(&self) -> Option<isize> {
+        self.write_param().map(|i| self.params[i].value())
+    }
+
+    pub fn encode(&self) -> Vec<isize> {
+        let mut opcode = self.opcode;
+        for (i, p) in self.params.iter().enumerate() {
+            opcode += p.mode() * 10isize.pow(i as u32 + 2);
+        }
+        let mut res = vec![opcode];
+        res.extend(self.params.iter().map(|p| p.value()));
+        res
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", mnemonic(self.opcode))?;
+        for (i, p) in self.params.iter().enumerate() {
+            let sep = if i == 0 { " " } else { ", " };
+            write!(f, "{}{}", sep, p)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn mnemonic(opcode: isize) -> &'static str {
+    match opcode {
+        OPCODE_ADD => "ADD",
+        OPCODE_MULT => "MULT",
+        OPCODE_INPUT => "INPUT",
+        OPCODE_OUTPUT => "OUTPUT",
+        OPCODE_JUMP_IF_TRUE => "JUMP_IF_TRUE",
+        OPCODE_JUMP_IF_FALSE => "JUMP_IF_FALSE",
+        OPCODE_LESS_THAN => "LESS_THAN",
+        OPCODE_EQUALS => "EQUALS",
+        OPCODE_HALT => "HALT",
+        _ => "???",
+    }
+}
+
+pub fn param_count(opcode: isize) -> Option<usize> {
+    match opcode {
+        OPCODE_ADD | OPCODE_MULT | OPCODE_LESS_THAN | OPCODE_EQUALS => Some(3),
+        OPCODE_INPUT | OPCODE_OUTPUT => Some(1),
+        OPCODE_JUMP_IF_TRUE | OPCODE_JUMP_IF_FALSE => Some(2),
+        OPCODE_HALT => Some(0),
+        _ => None,
+    }
+}
+
+pub fn decode(code: &[isize], addr: usize) -> Option<Instruction> {
+    let raw = *code.get(addr)?;
+    if raw < 0 {
+        return None;
+    }
+    let opcode = raw % 100;
+    let count = param_count(opcode)?;
+    let mut params = Vec::with_capacity(count);
+    for i in 0..count {
+        let value = *code.get(addr + 1 + i)?;
+        let mode = (raw / 10isize.pow(i as u32 + 2)) % 10;
+        params.push(match mode {
+            PMODE_POSITION => Param::Position(value),
+            PMODE_IMMEDIATE => Param::Immediate(value),
+            _ => return None,
+        });
+    }
+    Some(Instruction::new(addr, opcode, params))
+}
+
+// statically reachable instructions, following jumps from address 0
+pub struct Flow {
+    pub instructions: BTreeMap<usize, Instruction>,
+    pub jump_targets: BTreeSet<usize>,
+    pub written: BTreeSet<usize>,
+    // some write goes to an address that is itself computed at runtime
+    pub unknown_writes: bool,
+    // some jump target is computed at runtime
+    pub dynamic_jumps: bool,
+}
+
+impl Flow {
+    pub fn new(code: &[isize]) -> Self {
+        let mut flow = Flow {
+            instructions: BTreeMap::new(),
+            jump_targets: BTreeSet::new(),
+            written: BTreeSet::new(),
+            unknown_writes: false,
+            dynamic_jumps: false,
+        };
+
+        // position-mode jump targets are resolved as long as nothing writes
+        // to the cell they point at, so repeat until the write set is stable
+        loop {
+            flow.discover(code);
+            let before = flow.written.len();
+            for instr in flow.instructions.values() {
+                if let Some(target) = instr.write_target() {
+                    if target >= 0 && (target as usize) < code.len() {
+                        flow.written.insert(target as usize);
+                    }
+                }
+            }
+            if flow.written.len() == before {
+                break;
+            }
+        }
+
+        for instr in flow.instructions.values() {
+            if flow.is_written(instr.addr) {
+                flow.unknown_writes = true;
+            }
+            if let Some(i) = instr.write_param() {
+                if flow.is_written(instr.addr + 1 + i) {
+                    flow.unknown_writes = true;
+                }
+            }
+            if instr.is_jump() && flow.resolve_target(code, instr).is_none() {
+                flow.dynamic_jumps = true;
+            }
+        }
+        flow
+    }
+
+    pub fn is_written(&self, addr: usize) -> bool {
+        self.written.contains(&addr)
+    }
+
+    // true if no cell of the instruction can be changed by the program
+    pub fn is_clean(&self, instr: &Instruction) -> bool {
+        !self.unknown_writes && (instr.addr..instr.next()).all(|a| !self.is_written(a))
+    }
+
+    pub fn resolve_target(&self, code: &[isize], instr: &Instruction) -> Option<usize> {
+        if self.is_written(instr.addr + 2) {
+            return None;
+        }
+        let target = match instr.params[1] {
+            Param::Immediate(t) => t,
+            Param::Position(p) if p >= 0 && !self.is_written(p as usize) => *code.get(p as usize)?,
+            Param::Position(_) => return None,
+        };
+        if target >= 0 && (target as usize) < code.len() {
+            Some(target as usize)
+        } else {
+            None
+        }
+    }
+
+    fn discover(&mut self, code: &[isize]) {
+        let mut pending = vec![0];
+        let mut visited = BTreeSet::new();
+        while let Some(addr) = pending.pop() {
+            if !visited.insert(addr) {
+                continue;
+            }
+            let instr = match decode(code, addr) {
+                Some(instr) => instr,
+                None => continue,
+            };
+            if instr.is_jump() {
+                if let Some(target) = self.resolve_target(code, &instr) {
+                    self.jump_targets.insert(target);
+                    pending.push(target);
+                }
+                // a constant condition only has one successor
+                let always = match instr.params[0] {
+                    Param::Immediate(c) if !self.is_written(addr + 1) => {
+                        (c != 0) == (instr.opcode == OPCODE_JUMP_IF_TRUE)
+                    }
+                    _ => false,
+                };
+                if !always {
+                    pending.push(instr.next());
+                }
+            } else if instr.opcode != OPCODE_HALT {
+                pending.push(instr.next());
+            }
+            self.instructions.insert(addr, instr);
+        }
+    }
+}
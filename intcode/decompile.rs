@@ -0,0 +1,596 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::io;
+
+use crate::decode::*;
+use crate::host::*;
+use crate::vm::*;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum BinOp {
+    Add,
+    Mul,
+    Lt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl BinOp {
+    fn precedence(self) -> u32 {
+        match self {
+            BinOp::Mul => 3,
+            BinOp::Add => 2,
+            _ => 1,
+        }
+    }
+
+    fn negate(self) -> Option<BinOp> {
+        match self {
+            BinOp::Lt => Some(BinOp::Ge),
+            BinOp::Ge => Some(BinOp::Lt),
+            BinOp::Eq => Some(BinOp::Ne),
+            BinOp::Ne => Some(BinOp::Eq),
+            _ => None,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Mul => "*",
+            BinOp::Lt => "<",
+            BinOp::Ge => ">=",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Expr {
+    Const(isize),
+    Var(isize),
+    // mem[v], reading through an address the program computes
+    Load(isize),
+    Input,
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+impl Expr {
+    fn binary(op: BinOp, a: Expr, b: Expr) -> Expr {
+        Expr::Binary(op, Box::new(a), Box::new(b))
+    }
+
+    fn precedence(&self) -> u32 {
+        match self {
+            Expr::Binary(op, _, _) => op.precedence(),
+            _ => 4,
+        }
+    }
+
+    fn uses(&self, var: isize) -> bool {
+        match self {
+            Expr::Var(v) => *v == var,
+            // could be any cell
+            Expr::Load(_) => true,
+            Expr::Binary(_, a, b) => a.uses(var) || b.uses(var),
+            Expr::Not(e) => e.uses(var),
+            _ => false,
+        }
+    }
+
+    fn is_condition(&self) -> bool {
+        matches!(self, Expr::Binary(op, _, _) if op.negate().is_some())
+    }
+
+    // the jump conditions test for non-zero values
+    fn truthy(self) -> Expr {
+        if self.is_condition() {
+            self
+        } else {
+            Expr::binary(BinOp::Ne, self, Expr::Const(0))
+        }
+    }
+
+    fn negate(self) -> Expr {
+        match self {
+            Expr::Binary(op, a, b) if op.negate().is_some() => Expr::Binary(op.negate().unwrap(), a, b),
+            Expr::Not(e) => *e,
+            e => Expr::Not(Box::new(e)),
+        }
+    }
+
+    fn render(&self) -> String {
+        match self {
+            Expr::Const(c) => c.to_string(),
+            Expr::Var(v) => var_name(*v),
+            Expr::Load(v) => format!("mem[{}]", var_name(*v)),
+            Expr::Input => "input()".to_string(),
+            Expr::Not(e) => format!("!({})", e.render()),
+            Expr::Binary(BinOp::Add, a, b) if matches!(**b, Expr::Const(c) if c < 0) => {
+                let c = if let Expr::Const(c) = **b { c } else { unreachable!() };
+                format!("{} - {}", wrap(a, 2, false), c.unsigned_abs())
+            }
+            Expr::Binary(op, a, b) => {
+                let prec = op.precedence();
+                format!("{} {} {}", wrap(a, prec, false), op.symbol(), wrap(b, prec, true))
+            }
+        }
+    }
+}
+
+fn wrap(e: &Expr, prec: u32, right: bool) -> String {
+    if e.precedence() < prec || (right && e.precedence() == prec) {
+        format!("({})", e.render())
+    } else {
+        e.render()
+    }
+}
+
+fn var_name(addr: isize) -> String {
+    if addr < 0 {
+        format!("mem[{}]", addr)
+    } else {
+        format!("v{}", addr)
+    }
+}
+
+enum Stmt {
+    Assign(isize, Expr),
+    Output(Expr),
+    Halt,
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    Loop(Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    DoWhile(Vec<Stmt>, Expr),
+    Break,
+    Continue,
+    Goto(Option<Expr>, usize),
+    ComputedGoto(Option<Expr>, Expr),
+    Label(usize),
+    Comment(String),
+}
+
+enum Term {
+    Jump(Expr, usize),
+    ComputedJump(Expr, Expr),
+    Halt,
+    Fall,
+}
+
+struct Block {
+    start: usize,
+    end: usize,
+    stmts: Vec<Stmt>,
+    term: Term,
+}
+
+struct Decompiler<'a> {
+    code: &'a [isize],
+    flow: Flow,
+    reads: BTreeMap<isize, usize>,
+    writes: BTreeMap<isize, usize>,
+    blocks: BTreeMap<usize, Block>,
+    loops: Vec<(usize, usize)>, // (header, exit) of the enclosing loops
+    headers: BTreeSet<usize>,
+    gotos: BTreeSet<usize>,
+    consumed: BTreeSet<usize>, // blocks whose jump is part of an if/else
+}
+
+impl<'a> Decompiler<'a> {
+    fn new(code: &'a [isize]) -> Self {
+        let flow = Flow::new(code);
+        let mut reads = BTreeMap::new();
+        let mut writes = BTreeMap::new();
+        for instr in flow.instructions.values() {
+            for (i, p) in instr.params.iter().enumerate() {
+                if Some(i) == instr.write_param() {
+                    *writes.entry(p.value()).or_insert(0) += 1;
+                } else if let Param::Position(a) = p {
+                    *reads.entry(*a).or_insert(0) += 1;
+                }
+            }
+        }
+        Decompiler {
+            code,
+            flow,
+            reads,
+            writes,
+            blocks: BTreeMap::new(),
+            loops: Vec::new(),
+            headers: BTreeSet::new(),
+            gotos: BTreeSet::new(),
+            consumed: BTreeSet::new(),
+        }
+    }
+
+    // a cell written and read exactly once is an intermediate value whose
+    // expression is substituted into its use
+    fn is_temp(&self, addr: isize) -> bool {
+        !self.flow.unknown_writes
+            && !self.flow.dynamic_jumps
+            && self.reads.get(&addr) == Some(&1)
+            && self.writes.get(&addr) == Some(&1)
+            && (addr < 0 || !self.flow.instructions.contains_key(&(addr as usize)))
+    }
+
+    fn build_blocks(&mut self) {
+        let mut leaders = self.flow.jump_targets.clone();
+        leaders.insert(0);
+        for instr in self.flow.instructions.values() {
+            if instr.is_jump() || instr.opcode == OPCODE_HALT {
+                leaders.insert(instr.next());
+            }
+        }
+
+        let instructions = self.flow.instructions.values().cloned().collect::<Vec<Instruction>>();
+        let mut i = 0;
+        while i < instructions.len() {
+            let start = instructions[i].addr;
+            let mut pending: Vec<(isize, Expr)> = Vec::new();
+            let mut stmts = Vec::new();
+            let mut term = Term::Fall;
+            let mut end = start;
+            while i < instructions.len() {
+                let instr = &instructions[i];
+                if instr.addr != end || (instr.addr != start && leaders.contains(&instr.addr)) {
+                    break;
+                }
+                i += 1;
+                end = instr.next();
+                if !self.flow.is_clean(instr) {
+                    stmts.push(Stmt::Comment(format!("{}: instruction is modified at runtime", instr.addr)));
+                }
+                match instr.opcode {
+                    OPCODE_ADD | OPCODE_MULT | OPCODE_LESS_THAN | OPCODE_EQUALS => {
+                        let a = self.operand(instr, 0, &mut pending);
+                        let b = self.operand(instr, 1, &mut pending);
+                        let op = match instr.opcode {
+                            OPCODE_ADD => BinOp::Add,
+                            OPCODE_MULT => BinOp::Mul,
+                            OPCODE_LESS_THAN => BinOp::Lt,
+                            _ => BinOp::Eq,
+                        };
+                        let e = simplify(Expr::binary(op, a, b));
+                        self.assign(instr.params[2].value(), e, &mut pending, &mut stmts);
+                    }
+                    OPCODE_INPUT => {
+                        self.assign(instr.params[0].value(), Expr::Input, &mut pending, &mut stmts);
+                    }
+                    OPCODE_OUTPUT => {
+                        let e = self.operand(instr, 0, &mut pending);
+                        stmts.push(Stmt::Output(e));
+                    }
+                    OPCODE_JUMP_IF_TRUE | OPCODE_JUMP_IF_FALSE => {
+                        let c = self.operand(instr, 0, &mut pending).truthy();
+                        let c = if instr.opcode == OPCODE_JUMP_IF_TRUE { c } else { c.negate() };
+                        term = match self.flow.resolve_target(self.code, instr) {
+                            Some(target) => Term::Jump(c, target),
+                            None => Term::ComputedJump(c, self.operand(instr, 1, &mut pending)),
+                        };
+                        break;
+                    }
+                    _ => {
+                        term = Term::Halt;
+                        break;
+                    }
+                }
+            }
+            for (addr, e) in pending {
+                stmts.push(Stmt::Assign(addr, e));
+            }
+            self.blocks.insert(start, Block { start, end, stmts, term });
+        }
+    }
+
+    // a parameter cell the program writes to only has its value at runtime
+    fn operand(&self, instr: &Instruction, i: usize, pending: &mut Vec<(isize, Expr)>) -> Expr {
+        let cell = (instr.addr + 1 + i) as isize;
+        match instr.params[i] {
+            Param::Immediate(_) if self.flow.is_written(cell as usize) => Expr::Var(cell),
+            Param::Position(_) if self.flow.is_written(cell as usize) => Expr::Load(cell),
+            Param::Immediate(v) => Expr::Const(v),
+            Param::Position(a) => match pending.iter().position(|(t, _)| *t == a) {
+                Some(i) => pending.remove(i).1,
+                None => Expr::Var(a),
+            },
+        }
+    }
+
+    fn assign(&self, addr: isize, e: Expr, pending: &mut Vec<(isize, Expr)>, stmts: &mut Vec<Stmt>) {
+        // values that depend on the old content have to be computed first
+        let mut i = 0;
+        while i < pending.len() {
+            if pending[i].1.uses(addr) {
+                let (t, e) = pending.remove(i);
+                stmts.push(Stmt::Assign(t, e));
+            } else {
+                i += 1;
+            }
+        }
+        if self.is_temp(addr) && e != Expr::Input {
+            pending.push((addr, e));
+        } else {
+            stmts.push(Stmt::Assign(addr, e));
+        }
+    }
+
+    fn is_constant(&self, c: &Expr) -> Option<bool> {
+        match c {
+            Expr::Binary(BinOp::Ne, a, b) => match (&**a, &**b) {
+                (Expr::Const(a), Expr::Const(b)) => Some(a != b),
+                _ => None,
+            },
+            Expr::Binary(BinOp::Eq, a, b) => match (&**a, &**b) {
+                (Expr::Const(a), Expr::Const(b)) => Some(a == b),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // the first instruction at or after `addr`, skipping over data
+    fn code_at(&self, addr: usize) -> usize {
+        self.blocks.range(addr..).next().map_or(addr, |(&start, _)| start)
+    }
+
+    // the join point if the region [start, end) ends with an unconditional
+    // jump over a following else-branch
+    fn else_join(&self, start: usize, end: usize) -> Option<(usize, usize)> {
+        let (_, block) = self.blocks.range(start..end).next_back()?;
+        let join = match &block.term {
+            Term::Jump(c, target) if self.code_at(block.end) == end && self.is_constant(c) == Some(true) => *target,
+            _ => return None,
+        };
+        // jumps from the then-branch into the else-branch can't be structured
+        let crossing = self.blocks.range(start..end).any(|(_, b)| match b.term {
+            Term::Jump(_, t) => t >= end && t < join,
+            _ => false,
+        });
+        if crossing {
+            None
+        } else {
+            Some((block.start, join))
+        }
+    }
+
+    // the last block in [header, end) that jumps back to the header
+    fn latch(&self, header: usize, end: usize) -> Option<&Block> {
+        self.blocks
+            .range(header..end)
+            .map(|(_, b)| b)
+            .rev()
+            .find(|b| b.end <= end && matches!(b.term, Term::Jump(_, t) if t == header))
+    }
+
+    fn jump_stmt(&mut self, cond: Option<Expr>, target: usize, next: usize) -> Option<Stmt> {
+        if let Some(&(header, exit)) = self.loops.last() {
+            let stmt = if target == exit {
+                Some(Stmt::Break)
+            } else if target == header {
+                Some(Stmt::Continue)
+            } else {
+                None
+            };
+            if let Some(stmt) = stmt {
+                return Some(match cond {
+                    Some(c) => Stmt::If(c, vec![stmt], Vec::new()),
+                    None => stmt,
+                });
+            }
+        }
+        // data between the jump and its target is never executed
+        let next = self.blocks.range(next..).next().map_or(next, |(&start, _)| start);
+        if target == next && cond.is_none() {
+            return None;
+        }
+        self.gotos.insert(target);
+        Some(Stmt::Goto(cond, target))
+    }
+
+    fn region(&mut self, start: usize, end: usize) -> Vec<Stmt> {
+        let mut out = Vec::new();
+        let mut addr = start;
+        while addr < end {
+            let (start, block_end) = match self.blocks.range(addr..end).next() {
+                Some((&start, b)) => (start, b.end),
+                None => break,
+            };
+            if self.flow.jump_targets.contains(&start) {
+                out.push(Stmt::Label(start));
+            }
+
+            if !self.headers.contains(&start) {
+                if let Some(latch) = self.latch(start, end) {
+                    let (latch_start, latch_end) = (latch.start, latch.end);
+                    let cond = match &latch.term {
+                        Term::Jump(c, _) => c.clone(),
+                        _ => unreachable!(),
+                    };
+                    self.headers.insert(start);
+                    self.consumed.insert(latch_start);
+                    let exit = self.code_at(latch_end);
+                    self.loops.push((start, exit));
+                    let body = self.region(start, latch_end);
+                    self.loops.pop();
+                    let unconditional = self.is_constant(&cond) == Some(true);
+                    out.push(make_loop(body, cond, unconditional));
+                    addr = latch_end;
+                    continue;
+                }
+            }
+
+            let block = self.blocks.get_mut(&start).unwrap();
+            out.append(&mut block.stmts);
+            let term = std::mem::replace(&mut block.term, Term::Fall);
+            match term {
+                Term::Halt => out.push(Stmt::Halt),
+                Term::Fall => {}
+                _ if self.consumed.contains(&start) => {}
+                Term::ComputedJump(c, target) => {
+                    let c = if self.is_constant(&c) == Some(true) { None } else { Some(c) };
+                    out.push(Stmt::ComputedGoto(c, target));
+                }
+                Term::Jump(c, target) => match self.is_constant(&c) {
+                    Some(false) => {}
+                    Some(true) => out.extend(self.jump_stmt(None, target, block_end)),
+                    None if target > block_end && target <= end && !self.is_loop_jump(target) => {
+                        // forward jump over a then-branch, possibly followed by an else-branch
+                        let cond = c.negate();
+                        let else_end = match self.else_join(block_end, target) {
+                            Some((from, join)) if join > target && join <= end && !self.is_loop_jump(join) => {
+                                self.consumed.insert(from);
+                                Some(join)
+                            }
+                            _ => None,
+                        };
+                        let then = self.region(block_end, target);
+                        let (els, next) = match else_end {
+                            Some(join) => (self.region(target, join), join),
+                            None => (Vec::new(), target),
+                        };
+                        out.push(Stmt::If(cond, then, els));
+                        addr = next;
+                        continue;
+                    }
+                    None => out.extend(self.jump_stmt(Some(c), target, block_end)),
+                },
+            }
+            addr = block_end;
+        }
+        out
+    }
+
+    fn is_loop_jump(&self, target: usize) -> bool {
+        matches!(self.loops.last(), Some(&(header, exit)) if target == header || target == exit)
+    }
+}
+
+fn simplify(e: Expr) -> Expr {
+    match e {
+        Expr::Binary(op, a, b) if matches!((&*a, &*b), (Expr::Const(_), Expr::Const(_))) => {
+            let (a, b) = match (*a, *b) {
+                (Expr::Const(a), Expr::Const(b)) => (a, b),
+                _ => unreachable!(),
+            };
+            let value = match op {
+                BinOp::Add => a.checked_add(b),
+                BinOp::Mul => a.checked_mul(b),
+                BinOp::Lt => Some((a < b) as isize),
+                _ => Some((a == b) as isize),
+            };
+            match value {
+                Some(v) => Expr::Const(v),
+                None => Expr::binary(op, Expr::Const(a), Expr::Const(b)),
+            }
+        }
+        Expr::Binary(BinOp::Add, a, b) if *a == Expr::Const(0) => *b,
+        Expr::Binary(BinOp::Add, a, b) if *b == Expr::Const(0) => *a,
+        Expr::Binary(BinOp::Mul, a, b) if *a == Expr::Const(1) => *b,
+        Expr::Binary(BinOp::Mul, a, b) if *b == Expr::Const(1) => *a,
+        Expr::Binary(BinOp::Add, a, b) if matches!(*a, Expr::Const(_)) => Expr::Binary(BinOp::Add, b, a),
+        e => e,
+    }
+}
+
+// turns a loop body ending in the latch jump into while/do-while forms
+fn make_loop(mut body: Vec<Stmt>, cond: Expr, unconditional: bool) -> Stmt {
+    if !unconditional {
+        return Stmt::DoWhile(body, cond);
+    }
+    if let Some(Stmt::Label(_)) = body.first() {
+        body.remove(0);
+    }
+    if let Some(Stmt::If(c, then, els)) = body.first() {
+        if els.is_empty() && matches!(then.as_slice(), [Stmt::Break]) {
+            let c = c.clone();
+            body.remove(0);
+            return Stmt::While(c.negate(), body);
+        }
+    }
+    Stmt::Loop(body)
+}
+
+fn render(stmts: &[Stmt], gotos: &BTreeSet<usize>, depth: usize, out: &mut String) {
+    let indent = "    ".repeat(depth);
+    for stmt in stmts {
+        match stmt {
+            Stmt::Assign(a, e) => writeln!(out, "{}{} = {};", indent, var_name(*a), e.render()),
+            Stmt::Output(e) => writeln!(out, "{}output({});", indent, e.render()),
+            Stmt::Halt => writeln!(out, "{}halt;", indent),
+            Stmt::Break => writeln!(out, "{}break;", indent),
+            Stmt::Continue => writeln!(out, "{}continue;", indent),
+            Stmt::Comment(c) => writeln!(out, "{}// {}", indent, c),
+            Stmt::Label(l) if gotos.contains(l) => writeln!(out, "L{}:", l),
+            Stmt::Label(_) => Ok(()),
+            Stmt::Goto(None, l) => writeln!(out, "{}goto L{};", indent, l),
+            Stmt::Goto(Some(c), l) => writeln!(out, "{}if ({}) goto L{};", indent, c.render(), l),
+            Stmt::ComputedGoto(None, t) => writeln!(out, "{}goto *({});", indent, t.render()),
+            Stmt::ComputedGoto(Some(c), t) => writeln!(out, "{}if ({}) goto *({});", indent, c.render(), t.render()),
+            Stmt::If(c, then, els) => {
+                writeln!(out, "{}if ({}) {{", indent, c.render()).unwrap();
+                render(then, gotos, depth + 1, out);
+                if !els.is_empty() {
+                    writeln!(out, "{}}} else {{", indent).unwrap();
+                    render(els, gotos, depth + 1, out);
+                }
+                writeln!(out, "{}}}", indent)
+            }
+            Stmt::Loop(body) => {
+                writeln!(out, "{}loop {{", indent).unwrap();
+                render(body, gotos, depth + 1, out);
+                writeln!(out, "{}}}", indent)
+            }
+            Stmt::While(c, body) => {
+                writeln!(out, "{}while ({}) {{", indent, c.render()).unwrap();
+                render(body, gotos, depth + 1, out);
+                writeln!(out, "{}}}", indent)
+            }
+            Stmt::DoWhile(body, c) => {
+                writeln!(out, "{}do {{", indent).unwrap();
+                render(body, gotos, depth + 1, out);
+                writeln!(out, "{}}} while ({});", indent, c.render())
+            }
+        }
+        .unwrap();
+    }
+}
+
+pub fn decompile(code: &[isize]) -> String {
+    let mut d = Decompiler::new(code);
+    d.build_blocks();
+    let stmts = d.region(0, code.len());
+
+    let mut out = String::new();
+    if d.flow.instructions.values().any(|instr| !d.flow.is_clean(instr)) {
+        out.push_str("// warning: the program modifies its own code, this is the initial state\n");
+    }
+    if d.flow.dynamic_jumps {
+        out.push_str("// warning: the program jumps to computed addresses\n");
+    }
+    render(&stmts, &d.gotos, 0, &mut out);
+    out
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let program = load_program(args.first().map(String::as_str))?;
+    print!("{}", decompile(&program.code));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // day5's example: the input overwrites the jump's immediate condition
+    #[test]
+    fn modified_operand() {
+        let out = decompile(&[3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1]);
+        assert!(out.starts_with("// warning: the program modifies its own code"), "{}", out);
+        assert!(out.contains("2: instruction is modified at runtime"), "{}", out);
+        assert!(out.contains("if (v3 == 0) {\n    v12 = 0;\n}\noutput(v12);"), "{}", out);
+        assert!(!out.contains("goto"), "{}", out);
+    }
+}
@@ -0,0 +1,93 @@
+use std::io;
+
+use crate::decode::*;
+use crate::host::*;
+use crate::vm::*;
+
+// an output value with the instruction that produced it
+struct Output {
+    ip: usize,
+    instr: String,
+    value: isize,
+}
+
+// runs a day5 style diagnostic program: every output but the last is a test
+// result that must be zero, the last one is the diagnostic code
+fn collect(program: &mut IntCodeProgram, max_steps: usize) -> io::Result<Vec<Output>> {
+    let mut outputs = Vec::new();
+    for _ in 0..max_steps {
+        let ip = program.ip;
+        let state = program.step().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if let Some(value) = program.take_output() {
+            // as it was run, the program may have written it itself. outputs
+            // don't write memory, so it is still there
+            let instr = decode(&program.code, ip).map_or("?".to_string(), |i| i.to_string());
+            outputs.push(Output { ip, instr, value });
+        }
+        match state {
+            State::Running => {}
+            State::Halted => return Ok(outputs),
+            State::WaitingForInput => {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "program needs more input"));
+            }
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, format!("no halt after {} steps", max_steps)))
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut inputs = Vec::new();
+    let mut max_steps = 1_000_000;
+    let mut filename = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            // the system id, 1 for the air conditioner and 5 for the radiator
+            "--input" => {
+                let value = value(&mut iter, arg)?;
+                inputs.push(value.parse::<isize>().map_err(|_| invalid(format!("bad number {}", value)))?);
+            }
+            "--max-steps" => {
+                let value = value(&mut iter, arg)?;
+                max_steps = value.parse::<usize>().map_err(|_| invalid(format!("bad number {}", value)))?;
+            }
+            _ => filename = Some(arg.as_str()),
+        }
+    }
+    if inputs.is_empty() {
+        inputs.push(1);
+    }
+
+    let mut program = load_program(filename)?;
+    program.input = inputs;
+    let outputs = collect(&mut program, max_steps)?;
+
+    let (code_output, tests) = match outputs.split_last() {
+        Some(split) => split,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidData, "no diagnostic code, the program has no output")),
+    };
+    let failures = tests.iter().enumerate().filter(|(_, t)| t.value != 0).collect::<Vec<(usize, &Output)>>();
+    for (i, test) in &failures {
+        println!("test {} failed: output {} at ip {} ({})", i + 1, test.value, test.ip, test.instr);
+    }
+    if !failures.is_empty() {
+        return Err(io::Error::other(format!("{} of {} tests failed", failures.len(), tests.len())));
+    }
+    println!("{} tests passed, diagnostic code {}", tests.len(), code_output.value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modified_output() {
+        // the first instruction turns the halt at 4 into an output of 5
+        let mut program = "1101,104,0,4,99,5,104,7,99".parse::<IntCodeProgram>().unwrap();
+        let outputs = collect(&mut program, 100).unwrap();
+        let outputs = outputs.iter().map(|o| (o.ip, o.instr.as_str(), o.value)).collect::<Vec<_>>();
+        assert_eq!(outputs, vec![(4, "OUTPUT 5", 5), (6, "OUTPUT 7", 7)]);
+    }
+}
@@ -0,0 +1,612 @@
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::host::{invalid, value};
+use crate::vm::*;
+
+// xorshift64*, good enough to generate test programs
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    pub fn range(&mut self, lo: isize, hi: isize) -> isize {
+        lo + self.below((hi - lo) as usize) as isize
+    }
+
+    pub fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+}
+
+// what a given implementation can run
+struct Profile {
+    opcodes: &'static [isize],
+    // everything else makes the implementation crash, even when it only
+    // shows up by self-modification
+    supported: &'static [isize],
+    immediate: bool,
+    negative: bool,
+    prefix_inputs: usize,
+    final_output: bool,
+    fixed: &'static [(usize, isize)],
+}
+
+const DAY2: Profile = Profile {
+    opcodes: &[OPCODE_ADD, OPCODE_MULT],
+    supported: &[OPCODE_ADD, OPCODE_MULT],
+    immediate: false,
+    negative: false,
+    prefix_inputs: 0,
+    final_output: false,
+    // day2 always sets noun and verb to 12 and 2
+    fixed: &[(1, 12), (2, 2)],
+};
+
+const DAY5_PART1: Profile = Profile {
+    opcodes: &[OPCODE_ADD, OPCODE_MULT, OPCODE_INPUT, OPCODE_OUTPUT],
+    supported: &[OPCODE_ADD, OPCODE_MULT, OPCODE_INPUT, OPCODE_OUTPUT],
+    immediate: true,
+    negative: true,
+    prefix_inputs: 0,
+    final_output: false,
+    fixed: &[],
+};
+
+const DAY5_PART2: Profile = Profile {
+    opcodes: &[
+        OPCODE_ADD,
+        OPCODE_MULT,
+        OPCODE_INPUT,
+        OPCODE_OUTPUT,
+        OPCODE_JUMP_IF_TRUE,
+        OPCODE_JUMP_IF_FALSE,
+        OPCODE_LESS_THAN,
+        OPCODE_EQUALS,
+    ],
+    supported: &[
+        OPCODE_ADD,
+        OPCODE_MULT,
+        OPCODE_INPUT,
+        OPCODE_OUTPUT,
+        OPCODE_JUMP_IF_TRUE,
+        OPCODE_JUMP_IF_FALSE,
+        OPCODE_LESS_THAN,
+        OPCODE_EQUALS,
+    ],
+    immediate: true,
+    negative: true,
+    prefix_inputs: 0,
+    final_output: false,
+    fixed: &[],
+};
+
+// day7 feeds a phase and a signal and takes the first output
+const DAY7: Profile = Profile {
+    opcodes: &[
+        OPCODE_ADD,
+        OPCODE_MULT,
+        OPCODE_JUMP_IF_TRUE,
+        OPCODE_JUMP_IF_FALSE,
+        OPCODE_LESS_THAN,
+        OPCODE_EQUALS,
+    ],
+    supported: &[
+        OPCODE_ADD,
+        OPCODE_MULT,
+        OPCODE_INPUT,
+        OPCODE_OUTPUT,
+        OPCODE_JUMP_IF_TRUE,
+        OPCODE_JUMP_IF_FALSE,
+        OPCODE_LESS_THAN,
+        OPCODE_EQUALS,
+    ],
+    immediate: true,
+    negative: true,
+    prefix_inputs: 2,
+    final_output: true,
+    fixed: &[],
+};
+
+fn generate(rng: &mut Rng, profile: &Profile) -> Vec<isize> {
+    let mut opcodes = vec![OPCODE_INPUT; profile.prefix_inputs];
+    for _ in 0..1 + rng.below(12) {
+        opcodes.push(profile.opcodes[rng.below(profile.opcodes.len())]);
+    }
+    if profile.final_output {
+        opcodes.push(OPCODE_OUTPUT);
+    }
+    opcodes.push(OPCODE_HALT);
+
+    let mut starts = Vec::new();
+    let mut code_len = 0;
+    for &op in &opcodes {
+        starts.push(code_len);
+        code_len += match op {
+            OPCODE_ADD | OPCODE_MULT | OPCODE_LESS_THAN | OPCODE_EQUALS => 4,
+            OPCODE_INPUT | OPCODE_OUTPUT => 2,
+            OPCODE_JUMP_IF_TRUE | OPCODE_JUMP_IF_FALSE => 3,
+            _ => 1,
+        };
+    }
+    let len = (code_len + 4 + rng.below(8)).max(16);
+    // small values make equal operands and zero conditions likely
+    let lo = if profile.negative { -50 } else { 0 };
+    let value = |rng: &mut Rng| {
+        if rng.chance(70) {
+            rng.range(lo / 10, 5)
+        } else {
+            rng.range(lo, 50)
+        }
+    };
+
+    let mut code = (0..len).map(|_| value(rng)).collect::<Vec<isize>>();
+    // mostly data addresses, sometimes anything including the code itself
+    let address = |rng: &mut Rng| {
+        if rng.chance(10) {
+            rng.below(len) as isize
+        } else {
+            rng.range(code_len as isize, len as isize)
+        }
+    };
+    for (i, &op) in opcodes.iter().enumerate() {
+        let addr = starts[i];
+        let count = match op {
+            OPCODE_ADD | OPCODE_MULT | OPCODE_LESS_THAN | OPCODE_EQUALS => 3,
+            OPCODE_INPUT | OPCODE_OUTPUT => 1,
+            OPCODE_JUMP_IF_TRUE | OPCODE_JUMP_IF_FALSE => 2,
+            _ => 0,
+        };
+        let mut opcode = op;
+        for p in 0..count {
+            let is_write = (count == 3 && p == 2) || op == OPCODE_INPUT;
+            let is_target = (op == OPCODE_JUMP_IF_TRUE || op == OPCODE_JUMP_IF_FALSE) && p == 1;
+            let immediate = !is_write && profile.immediate && rng.chance(50);
+            let value = if is_target {
+                let target = starts[rng.below(starts.len())] as isize;
+                if immediate {
+                    target
+                } else {
+                    let cell = rng.range(code_len as isize, len as isize);
+                    code[cell as usize] = target;
+                    cell
+                }
+            } else if immediate {
+                value(rng)
+            } else {
+                address(rng)
+            };
+            if immediate {
+                opcode += 10isize.pow(p as u32 + 2);
+            }
+            code[addr + 1 + p] = value;
+        }
+        code[addr] = opcode;
+    }
+    for &(addr, value) in profile.fixed {
+        code[addr] = value;
+    }
+    code
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Status {
+    Halted,
+    Crashed,
+    Timeout,
+    NeedsInput,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Outcome {
+    status: Status,
+    output: Vec<isize>,
+    memory: Vec<(usize, isize)>, // only the cells the implementation shows
+}
+
+impl Outcome {
+    fn matches(&self, other: &Outcome) -> bool {
+        if self.status != other.status {
+            return false;
+        }
+        // outputs and memory are compared unless the run was cut short
+        self.status == Status::Timeout || (self.output == other.output && self.memory == other.memory)
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.status)?;
+        if !self.output.is_empty() {
+            let shown = self.output.iter().take(10).map(|o| o.to_string()).collect::<Vec<String>>();
+            let more = if self.output.len() > 10 { ", ..." } else { "" };
+            write!(f, ", {} outputs [{}{}]", self.output.len(), shown.join(", "), more)?;
+        }
+        for (addr, value) in &self.memory {
+            write!(f, ", [{}] = {}", addr, value)?;
+        }
+        Ok(())
+    }
+}
+
+// runs the shared machine with a step budget, treating faults as crashes
+fn run_vm(code: &[isize], inputs: &[isize], max_steps: usize, profile: &Profile) -> (Status, IntCodeProgram) {
+    let mut program = IntCodeProgram::new(code.to_vec());
+    program.input = inputs.to_vec();
+    for _ in 0..max_steps {
+        // implementations without parameter modes don't strip them
+        let opcode = program
+            .code
+            .get(program.ip)
+            .map(|&op| if profile.immediate { op % 100 } else { op });
+        if let Some(op) = opcode {
+            if op != OPCODE_HALT && !profile.supported.contains(&op) {
+                return (Status::Crashed, program);
+            }
+        }
+        match program.step() {
+            Ok(State::Running) => {}
+            Ok(State::Halted) => return (Status::Halted, program),
+            Ok(State::WaitingForInput) => return (Status::NeedsInput, program),
+            Err(_) => return (Status::Crashed, program),
+        }
+    }
+    (Status::Timeout, program)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Kind {
+    Day2,
+    Day5Part1,
+    Day5Part2,
+    Day7,
+}
+
+struct Implementation {
+    kind: Kind,
+    name: &'static str,
+    binary: String,
+}
+
+impl Implementation {
+    fn profile(&self) -> &'static Profile {
+        match self.kind {
+            Kind::Day2 => &DAY2,
+            Kind::Day5Part1 => &DAY5_PART1,
+            Kind::Day5Part2 => &DAY5_PART2,
+            Kind::Day7 => &DAY7,
+        }
+    }
+
+    // what the shared machine says, as far as this implementation shows it
+    fn reference(&self, code: &[isize], inputs: &[isize], max_steps: usize) -> Outcome {
+        match self.kind {
+            Kind::Day2 => {
+                let (status, program) = run_vm(code, &[], max_steps, &DAY2);
+                let memory = if status == Status::Halted {
+                    vec![(0, program.code[0])]
+                } else {
+                    Vec::new()
+                };
+                Outcome {
+                    status,
+                    output: Vec::new(),
+                    memory,
+                }
+            }
+            Kind::Day5Part1 | Kind::Day5Part2 => {
+                let (status, program) = run_vm(code, inputs, max_steps, self.profile());
+                Outcome {
+                    status,
+                    output: program.output,
+                    memory: Vec::new(),
+                }
+            }
+            Kind::Day7 => {
+                let mut phases = [0, 1, 2, 3, 4];
+                let mut max_output = isize::MIN;
+                loop {
+                    let mut signal = 0;
+                    for &phase in &phases {
+                        let (status, program) = run_vm(code, &[phase, signal], max_steps, &DAY7);
+                        // day7 crashes when asking for a third input or without output
+                        let status = match status {
+                            Status::NeedsInput => Status::Crashed,
+                            Status::Halted if program.output.is_empty() => Status::Crashed,
+                            s => s,
+                        };
+                        if status != Status::Halted {
+                            return Outcome {
+                                status,
+                                output: Vec::new(),
+                                memory: Vec::new(),
+                            };
+                        }
+                        signal = program.output[0];
+                    }
+                    max_output = max_output.max(signal);
+                    if !next_permutation(&mut phases) {
+                        break;
+                    }
+                }
+                Outcome {
+                    status: Status::Halted,
+                    output: vec![max_output],
+                    memory: Vec::new(),
+                }
+            }
+        }
+    }
+
+    fn run(&self, code: &[isize], inputs: &[isize], timeout: Duration) -> io::Result<Outcome> {
+        let mut stdin = format_code(code);
+        if self.kind == Kind::Day5Part1 || self.kind == Kind::Day5Part2 {
+            stdin.push('\n');
+            for i in inputs {
+                stdin.push_str(&format!("{}\n", i));
+            }
+        }
+
+        let mut child = Command::new(&self.binary)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        // day2 parses the line as is, so it must not end in a newline
+        child.stdin.take().unwrap().write_all(stdin.as_bytes())?;
+        let mut stdout = child.stdout.take().unwrap();
+        let reader = thread::spawn(move || {
+            let mut text = String::new();
+            let _ = (&mut stdout).take(1 << 20).read_to_string(&mut text);
+            text
+        });
+
+        let start = Instant::now();
+        let exit = loop {
+            if let Some(exit) = child.try_wait()? {
+                break Some(exit);
+            }
+            if start.elapsed() > timeout {
+                child.kill()?;
+                child.wait()?;
+                break None;
+            }
+            thread::sleep(Duration::from_millis(1));
+        };
+        let text = reader.join().unwrap_or_default();
+
+        let status = match exit {
+            None => Status::Timeout,
+            Some(exit) if exit.success() => Status::Halted,
+            Some(_) => Status::Crashed,
+        };
+        let mut outcome = Outcome {
+            status,
+            output: Vec::new(),
+            memory: Vec::new(),
+        };
+        for line in text.lines() {
+            match self.kind {
+                Kind::Day2 if status == Status::Halted => {
+                    outcome.memory.push((0, line.trim().parse().unwrap_or_default()));
+                }
+                // input prompts are printed without a newline
+                Kind::Day5Part1 | Kind::Day5Part2 => {
+                    if let Some(i) = line.rfind("output: ") {
+                        outcome.output.push(line[i + 8..].trim().parse().unwrap_or_default());
+                    }
+                }
+                Kind::Day7 if status == Status::Halted => {
+                    outcome.output.push(line.trim().parse().unwrap_or_default());
+                }
+                _ => {}
+            }
+        }
+        Ok(outcome)
+    }
+}
+
+fn next_permutation(values: &mut [isize]) -> bool {
+    let i = match (1..values.len()).rev().find(|&i| values[i - 1] < values[i]) {
+        Some(i) => i,
+        None => return false,
+    };
+    let j = (i..values.len()).rev().find(|&j| values[j] > values[i - 1]).unwrap();
+    values.swap(i - 1, j);
+    values[i..].reverse();
+    true
+}
+
+struct Harness {
+    max_steps: usize,
+    timeout: Duration,
+}
+
+impl Harness {
+    // None if the program doesn't show anything, e.g. it runs too long
+    fn compare(&self, imp: &Implementation, code: &[isize], inputs: &[isize]) -> io::Result<Option<(Outcome, Outcome)>> {
+        let expected = imp.reference(code, inputs, self.max_steps);
+        if expected.status == Status::Timeout || expected.status == Status::NeedsInput {
+            return Ok(None);
+        }
+        let actual = imp.run(code, inputs, self.timeout)?;
+        if expected.matches(&actual) {
+            Ok(None)
+        } else {
+            Ok(Some((expected, actual)))
+        }
+    }
+
+    fn still_fails(&self, imp: &Implementation, code: &[isize], inputs: &[isize]) -> io::Result<bool> {
+        if imp.profile().fixed.iter().any(|&(addr, value)| code.get(addr) != Some(&value)) {
+            return Ok(false);
+        }
+        Ok(self.compare(imp, code, inputs)?.is_some())
+    }
+
+    // removes and simplifies cells as long as the difference remains
+    fn shrink(&self, imp: &Implementation, code: &[isize], inputs: &[isize]) -> io::Result<Vec<isize>> {
+        let mut code = code.to_vec();
+        let mut attempts = 0;
+        let mut progress = true;
+        while progress && attempts < 2000 {
+            progress = false;
+            for i in (0..code.len()).rev() {
+                let mut candidate = code.clone();
+                candidate.remove(i);
+                attempts += 1;
+                if self.still_fails(imp, &candidate, inputs)? {
+                    code = candidate;
+                    progress = true;
+                }
+            }
+            for i in 0..code.len() {
+                for &value in &[0, code[i] / 2] {
+                    if value == code[i] {
+                        continue;
+                    }
+                    let mut candidate = code.clone();
+                    candidate[i] = value;
+                    attempts += 1;
+                    if self.still_fails(imp, &candidate, inputs)? {
+                        code = candidate;
+                        progress = true;
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(code)
+    }
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut count = 200;
+    let mut seed = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(1, |d| d.as_nanos() as u64);
+    let mut harness = Harness {
+        max_steps: 10_000,
+        timeout: Duration::from_millis(500),
+    };
+    let mut implementations = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value = value(&mut iter, arg)?;
+        let number = || value.parse::<u64>().map_err(|_| invalid(format!("bad number {}", value)));
+        let (kind, name) = match arg.as_str() {
+            "--count" => {
+                count = number()?;
+                continue;
+            }
+            "--seed" => {
+                seed = number()?;
+                continue;
+            }
+            "--max-steps" => {
+                harness.max_steps = number()? as usize;
+                continue;
+            }
+            "--timeout-ms" => {
+                harness.timeout = Duration::from_millis(number()?);
+                continue;
+            }
+            // paths to the built binaries of the day solutions. day7 needs a
+            // crate to build, difftest/day7.rs builds it with rustc alone
+            "--day2" => (Kind::Day2, "day2"),
+            "--day5-part1" => (Kind::Day5Part1, "day5/part1"),
+            "--day5" => (Kind::Day5Part2, "day5/part2"),
+            "--day7" => (Kind::Day7, "day7/part1"),
+            _ => return Err(invalid(format!("unknown option {}", arg))),
+        };
+        implementations.push(Implementation {
+            kind,
+            name,
+            binary: value.clone(),
+        });
+    }
+    if implementations.is_empty() {
+        return Err(invalid(
+            "no implementations given, use --day2, --day5-part1, --day5 or --day7".to_string(),
+        ));
+    }
+
+    run_programs(&harness, &implementations, count, seed)
+}
+
+fn run_programs(harness: &Harness, implementations: &[Implementation], count: u64, seed: u64) -> io::Result<()> {
+    println!("seed {}", seed);
+    let mut rng = Rng(seed.max(1));
+    let mut failures = 0;
+    for imp in implementations {
+        let mut checked = 0;
+        for _ in 0..count {
+            let code = generate(&mut rng, imp.profile());
+            let inputs = (0..16).map(|_| rng.range(-50, 50)).collect::<Vec<isize>>();
+            checked += 1;
+            if harness.compare(imp, &code, &inputs)?.is_none() {
+                continue;
+            }
+
+            failures += 1;
+            println!("{}: difference found", imp.name);
+            println!("  program: {}", format_code(&code));
+            let code = harness.shrink(imp, &code, &inputs)?;
+            println!("  shrunk:  {}", format_code(&code));
+            println!("  inputs:  {}", format_code(&inputs));
+            println!("  vm:      {}", imp.reference(&code, &inputs, harness.max_steps));
+            println!("  {:<8} {}", format!("{}:", imp.name), imp.run(&code, &inputs, harness.timeout)?);
+            break;
+        }
+        println!("{}: {} programs checked", imp.name, checked);
+    }
+    if failures > 0 {
+        return Err(io::Error::other(format!("{} implementations differ", failures)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn day7() {
+        let binary = env::temp_dir().join(format!("intcode-day7-{}", std::process::id()));
+        let source = Path::new(file!()).with_file_name("difftest").join("day7.rs");
+        let build = Command::new("rustc").args(["--edition", "2018", "-O"]).arg(&source).arg("-o").arg(&binary).output().unwrap();
+        assert!(build.status.success(), "{}", String::from_utf8_lossy(&build.stderr));
+
+        let harness = Harness {
+            max_steps: 10_000,
+            timeout: Duration::from_secs(5),
+        };
+        let imp = Implementation {
+            kind: Kind::Day7,
+            name: "day7/part1",
+            binary: binary.to_string_lossy().into_owned(),
+        };
+        let example = "3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0".parse::<IntCodeProgram>().unwrap().code;
+        assert_eq!(imp.run(&example, &[], harness.timeout).unwrap().output, vec![43210]);
+        let result = run_programs(&harness, &[imp], 50, 7);
+        let _ = std::fs::remove_file(&binary);
+        result.unwrap();
+    }
+}
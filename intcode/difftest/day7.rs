@@ -0,0 +1,26 @@
+// builds day7/part1 for `intcode difftest --day7` without cargo:
+//
+//     rustc --edition 2018 -O difftest/day7.rs -o day7
+//
+// day7 only takes next_permutation from the permutohedron crate, this is the
+// same lexical order over a slice
+mod permutohedron {
+    pub trait LexicalPermutation {
+        fn next_permutation(&mut self) -> bool;
+    }
+
+    impl<T: Ord> LexicalPermutation for [T] {
+        fn next_permutation(&mut self) -> bool {
+            let i = match (1..self.len()).rev().find(|&i| self[i - 1] < self[i]) {
+                Some(i) => i,
+                None => return false,
+            };
+            let j = (i..self.len()).rev().find(|&j| self[j] > self[i - 1]).unwrap();
+            self.swap(i - 1, j);
+            self[i..].reverse();
+            true
+        }
+    }
+}
+
+include!("../../day7/part1.rs");
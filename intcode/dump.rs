@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::io::IsTerminal;
+
+use crate::decode::*;
+use crate::host::*;
+use crate::vm::*;
+
+const YELLOW: &str = "\x1b[1;33m";
+const RESET: &str = "\x1b[0m";
+
+// the instruction that last wrote a cell, and when
+struct Writer {
+    step: u64,
+    instr: Instruction,
+}
+
+// runs to the end keeping track of who wrote what
+fn execute(program: &mut IntCodeProgram, max_steps: u64) -> (String, HashMap<usize, Writer>) {
+    let mut writers = HashMap::new();
+    let mut steps = 0;
+    let status = loop {
+        if steps == max_steps {
+            break "step limit".to_string();
+        }
+        let instr = decode(&program.code, program.ip);
+        match program.step() {
+            Ok(State::Running) => {
+                steps += 1;
+                if let Some(instr) = instr {
+                    if let Some(target) = instr.write_target() {
+                        writers.insert(target as usize, Writer { step: steps, instr });
+                    }
+                }
+            }
+            Ok(State::Halted) => break "halted".to_string(),
+            Ok(State::WaitingForInput) => break "needs input".to_string(),
+            Err(fault) => break fault.to_string(),
+        }
+    };
+    (format!("{} after {} steps", status, steps), writers)
+}
+
+// memory in rows of `columns` cells, each row labelled with its first
+// address. changed cells are highlighted, or marked with * when the output
+// isn't a terminal
+fn grid(code: &[isize], columns: usize, changed: &dyn Fn(usize) -> bool) -> String {
+    let color = io::stdout().is_terminal();
+    let width = code.iter().map(|v| v.to_string().len()).max().unwrap_or(1);
+    let label = code.len().saturating_sub(1).to_string().len();
+    let mut out = String::new();
+    for (r, row) in code.chunks(columns).enumerate() {
+        let _ = write!(out, "{:>label$}:", r * columns, label = label);
+        for (i, value) in row.iter().enumerate() {
+            let addr = r * columns + i;
+            match (changed(addr), color) {
+                (true, true) => {
+                    let _ = write!(out, " {}{:>width$}{} ", YELLOW, value, RESET, width = width);
+                }
+                (true, false) => {
+                    let _ = write!(out, " {:>width$}*", value, width = width);
+                }
+                (false, _) => {
+                    let _ = write!(out, " {:>width$} ", value, width = width);
+                }
+            }
+        }
+        out.truncate(out.trim_end().len());
+        out.push('\n');
+    }
+    out
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut execute_first = false;
+    let mut diff = false;
+    let mut inputs = Vec::new();
+    let mut noun = None;
+    let mut verb = None;
+    let mut columns = 10;
+    let mut max_steps = 1_000_000;
+    let mut save = None;
+    let mut files = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let number = |s: String| s.parse::<isize>().map_err(|_| invalid(format!("bad number {}", s)));
+        match arg.as_str() {
+            // dump the memory after running the program instead of before
+            "--run" => execute_first = true,
+            // compare the memory before and after running, or two snapshots
+            "--diff" => diff = true,
+            "--input" => inputs.push(number(value(&mut iter, arg)?)?),
+            // day2 puts these in cells 1 and 2 before running
+            "--noun" => noun = Some(number(value(&mut iter, arg)?)?),
+            "--verb" => verb = Some(number(value(&mut iter, arg)?)?),
+            "--columns" => columns = number(value(&mut iter, arg)?)?.max(1) as usize,
+            "--max-steps" => max_steps = number(value(&mut iter, arg)?)?.max(0) as u64,
+            // the memory after running, as a program for later diffs
+            "--save" => save = Some(value(&mut iter, arg)?),
+            _ => files.push(arg.as_str()),
+        }
+    }
+
+    // only a run leaves memory to save, comparing snapshots doesn't run anything
+    if save.is_some() && (files.len() == 2 || !(execute_first || diff)) {
+        return Err(invalid("--save needs --run or --diff with one program".to_string()));
+    }
+
+    // two snapshots are compared as they are, who wrote what is unknown
+    if let [before, after] = files.as_slice() {
+        if !diff {
+            return Err(invalid("two files only make sense with --diff".to_string()));
+        }
+        let before = load_program(Some(before))?.code;
+        let after = load_program(Some(after))?.code;
+        print!("{}", report(&before, &after, &HashMap::new(), columns));
+        return Ok(());
+    }
+    if files.len() > 1 {
+        return Err(invalid("usage: intcode dump [options] [program] [snapshot]".to_string()));
+    }
+
+    let mut program = load_program(files.first().cloned())?;
+    for (addr, value) in [(1, noun), (2, verb)] {
+        if let Some(value) = value {
+            *program.code.get_mut(addr).ok_or_else(|| invalid("the program is too short for noun and verb".to_string()))? = value;
+        }
+    }
+    program.input = inputs;
+    let before = program.code.clone();
+
+    if !execute_first && !diff {
+        print!("{}", grid(&before, columns, &|_| false));
+        return Ok(());
+    }
+    let (status, writers) = execute(&mut program, max_steps);
+    println!("{}", status);
+    if diff {
+        print!("{}", report(&before, &program.code, &writers, columns));
+    } else {
+        print!("{}", grid(&program.code, columns, &|_| false));
+    }
+    if let Some(save) = save {
+        fs::write(save, format_code(&program.code) + "\n")?;
+    }
+    Ok(())
+}
+
+// the new memory with changed cells highlighted, then one line per change
+fn report(before: &[isize], after: &[isize], writers: &HashMap<usize, Writer>, columns: usize) -> String {
+    let changed = |addr: usize| before.get(addr) != after.get(addr);
+    let mut out = grid(after, columns, &changed);
+    let mut count = 0;
+    for addr in (0..before.len().max(after.len())).filter(|&a| changed(a)) {
+        count += 1;
+        let show = |v: Option<&isize>| v.map_or("-".to_string(), |v| v.to_string());
+        let _ = write!(out, "{:6}: {} -> {}", addr, show(before.get(addr)), show(after.get(addr)));
+        if let Some(writer) = writers.get(&addr) {
+            let _ = write!(out, ", last written at step {} by {} at {}", writer.step, writer.instr, writer.instr.addr);
+        }
+        out.push('\n');
+    }
+    let _ = writeln!(out, "{} of {} cells changed", count, after.len());
+    out
+}
@@ -0,0 +1,73 @@
+// a single threaded executor for async machines when there is no runtime
+// around, e.g. in tests. it polls every task that was woken until they have
+// all finished or none of them can go on
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Waker};
+
+// set when the task should be polled again
+struct Woken(AtomicBool);
+
+impl Wake for Woken {
+    fn wake(self: Arc<Self>) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+struct Task<'a> {
+    future: Pin<Box<dyn Future<Output = ()> + 'a>>,
+    woken: Arc<Woken>,
+}
+
+// tasks may borrow from the caller, to leave their results somewhere
+#[derive(Default)]
+pub struct Executor<'a> {
+    tasks: Vec<Task<'a>>,
+}
+
+impl<'a> Executor<'a> {
+    pub fn new() -> Self {
+        Executor::default()
+    }
+
+    pub fn spawn(&mut self, future: impl Future<Output = ()> + 'a) {
+        self.tasks.push(Task {
+            future: Box::pin(future),
+            woken: Arc::new(Woken(AtomicBool::new(true))),
+        });
+    }
+
+    // returns how many tasks are left waiting, 0 when all of them finished.
+    // waiting tasks are either deadlocked or wait for something outside the
+    // executor, run can be called again once that has woken them
+    pub fn run(&mut self) -> usize {
+        loop {
+            let mut polled = false;
+            let mut i = 0;
+            while i < self.tasks.len() {
+                let task = &mut self.tasks[i];
+                if task.woken.0.swap(false, Ordering::SeqCst) {
+                    polled = true;
+                    let waker = Waker::from(task.woken.clone());
+                    if task.future.as_mut().poll(&mut Context::from_waker(&waker)).is_ready() {
+                        self.tasks.swap_remove(i);
+                        continue;
+                    }
+                }
+                i += 1;
+            }
+            if !polled {
+                return self.tasks.len();
+            }
+        }
+    }
+}
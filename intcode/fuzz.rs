@@ -0,0 +1,482 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+
+use crate::decode::*;
+use crate::difftest::Rng;
+use crate::host::{invalid, value};
+use crate::vm::*;
+
+const MAX_LEN: usize = 4096;
+const MAX_STEPS: usize = 10_000;
+
+// values that tend to reach new code: opcodes with every mode, halt, and
+// the edges of the cell type
+const INTERESTING: &[isize] = &[
+    0,
+    1,
+    -1,
+    2,
+    3,
+    4,
+    5,
+    6,
+    7,
+    8,
+    99,
+    101,
+    1001,
+    1101,
+    104,
+    1105,
+    1106,
+    1107,
+    1108,
+    10001,
+    203,
+    299,
+    i32::MAX as isize,
+    isize::MAX,
+    isize::MIN,
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Target {
+    Parse,
+    Decode,
+    Execute,
+}
+
+const TARGETS: &[Target] = &[Target::Parse, Target::Decode, Target::Execute];
+
+impl Target {
+    fn name(self) -> &'static str {
+        match self {
+            Target::Parse => "parse",
+            Target::Decode => "decode",
+            Target::Execute => "execute",
+        }
+    }
+
+    // runs one input and returns what it covered. there is no compiler
+    // instrumentation, so coverage is what the parser and machine report
+    fn run(self, data: &str) -> HashSet<u64> {
+        let mut features = HashSet::new();
+
+        let parsed = data.parse::<IntCodeProgram>();
+        match self {
+            Target::Parse => {
+                // character class transitions stand in for parser branches
+                let class = |c: char| match c {
+                    '0'..='9' => 0,
+                    '-' | '+' => 1,
+                    ',' => 2,
+                    '\n' => 3,
+                    c if c.is_whitespace() => 4,
+                    '#' => 5,
+                    _ => 6,
+                };
+                let mut prev = 7;
+                for c in data.chars() {
+                    feature(&mut features, ("class", prev, class(c)));
+                    prev = class(c);
+                }
+                match &parsed {
+                    Ok(program) => feature(&mut features, ("ok", bucket(program.code.len()))),
+                    Err(e) => feature(&mut features, ("err", format!("{:?}", e.problem))),
+                }
+            }
+            Target::Decode => {
+                let code = match parsed {
+                    Ok(program) => program.code,
+                    Err(_) => return features,
+                };
+                for addr in 0..code.len() {
+                    match decode(&code, addr) {
+                        Some(instr) => {
+                            let text = instr.to_string();
+                            feature(&mut features, ("instr", instr.encode()[0], text.len()));
+                        }
+                        None => feature(&mut features, ("data", code[addr].signum())),
+                    }
+                }
+                let flow = Flow::new(&code);
+                feature(
+                    &mut features,
+                    ("flow", bucket(flow.instructions.len()), bucket(flow.jump_targets.len())),
+                );
+                feature(&mut features, ("flags", flow.unknown_writes, flow.dynamic_jumps));
+                for instr in flow.instructions.values() {
+                    let clean = flow.is_clean(instr);
+                    let target = if instr.is_jump() { flow.resolve_target(&code, instr) } else { None };
+                    feature(&mut features, ("reachable", instr.opcode, clean, target.is_some()));
+                }
+            }
+            Target::Execute => {
+                let mut program = match parsed {
+                    Ok(program) => program,
+                    Err(_) => return features,
+                };
+                // inputs are derived from the data so replays are exact
+                let mut hasher = DefaultHasher::new();
+                data.hash(&mut hasher);
+                let mut rng = Rng(hasher.finish() | 1);
+                program.input = (0..16).map(|_| rng.range(-10, 10)).collect();
+
+                // edges between instructions, with hit counts in buckets
+                let mut edges = HashMap::new();
+                let mut prev = program.ip;
+                let mut end = None;
+                for _ in 0..MAX_STEPS {
+                    match program.step() {
+                        Ok(State::Running) => {
+                            *edges.entry((prev, program.ip)).or_insert(0) += 1;
+                            prev = program.ip;
+                        }
+                        state => {
+                            end = Some(state);
+                            break;
+                        }
+                    }
+                }
+                for ((from, to), count) in edges {
+                    feature(&mut features, ("edge", from, to, bucket(count)));
+                }
+                let end = match end {
+                    Some(Ok(state)) => format!("{:?}", state),
+                    Some(Err(fault)) => format!("{:?}", fault).split(' ').next().unwrap_or_default().to_string(),
+                    None => "timeout".to_string(),
+                };
+                feature(&mut features, ("end", &end, bucket(program.output.len())));
+            }
+        }
+        features
+    }
+
+    // runs the input and returns the panic message if there was one
+    fn crash(self, data: &str) -> Result<HashSet<u64>, String> {
+        panic::catch_unwind(|| self.run(data)).map_err(|payload| {
+            payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default()
+        })
+    }
+}
+
+fn feature<T: Hash>(features: &mut HashSet<u64>, value: T) {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    features.insert(hasher.finish());
+}
+
+// powers of two, like afl's hit count buckets
+fn bucket(n: usize) -> u32 {
+    usize::BITS - n.leading_zeros()
+}
+
+fn mutate(rng: &mut Rng, data: &str, corpus: &[String]) -> String {
+    let mut cells = data.split(',').map(String::from).collect::<Vec<String>>();
+    for _ in 0..1 + rng.below(4) {
+        let i = rng.below(cells.len());
+        let number = cells[i].trim().parse::<isize>().ok();
+        match rng.below(9) {
+            0 => cells[i] = INTERESTING[rng.below(INTERESTING.len())].to_string(),
+            1 => cells[i] = rng.range(-64, 64).to_string(),
+            2 => cells[i] = rng.below(cells.len() + 4).to_string(),
+            3 => cells[i] = cells[rng.below(cells.len())].clone(),
+            4 => cells.insert(i, INTERESTING[rng.below(INTERESTING.len())].to_string()),
+            5 if cells.len() > 1 => {
+                cells.remove(i);
+            }
+            // flip one of the mode digits
+            6 => {
+                if let Some(n) = number {
+                    let digit = [100, 1000, 10000][rng.below(3)];
+                    cells[i] = n.wrapping_add(if rng.chance(50) { digit } else { -digit }).to_string();
+                }
+            }
+            // splice with another entry
+            7 => {
+                let other = &corpus[rng.below(corpus.len())];
+                let other = other.split(',').collect::<Vec<&str>>();
+                let from = rng.below(other.len());
+                cells.truncate(i);
+                cells.extend(other[from..].iter().map(|s| s.to_string()));
+            }
+            // raw text, mostly for the parser
+            _ => {
+                let mut bytes = cells[i].clone().into_bytes();
+                let at = rng.below(bytes.len() + 1);
+                match rng.below(3) {
+                    0 => bytes.insert(at, b"0123456789-+, \n\t#x"[rng.below(18)]),
+                    1 if at < bytes.len() => {
+                        bytes.remove(at);
+                    }
+                    _ => bytes.extend_from_within(..at),
+                }
+                cells[i] = String::from_utf8_lossy(&bytes).to_string();
+            }
+        }
+        if cells.is_empty() {
+            cells.push(String::new());
+        }
+    }
+    let mut res = cells.join(",");
+    if res.len() > MAX_LEN {
+        let mut end = MAX_LEN;
+        while !res.is_char_boundary(end) {
+            end -= 1;
+        }
+        res.truncate(end);
+    }
+    res
+}
+
+fn hash_name(data: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+fn read_dir(dir: &Path) -> io::Result<Vec<(PathBuf, String)>> {
+    let mut res = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            let bytes = fs::read(&path)?;
+            res.push((path, String::from_utf8_lossy(&bytes).to_string()));
+        }
+    }
+    res.sort();
+    Ok(res)
+}
+
+// runs every file through every target; crashes that were found and fixed
+// are kept this way as regression tests
+fn replay(dirs: &[PathBuf]) -> io::Result<()> {
+    let mut failures = 0;
+    let mut count = 0;
+    for dir in dirs {
+        for (path, data) in read_dir(dir)? {
+            count += 1;
+            for &target in TARGETS {
+                if let Err(msg) = target.crash(&data) {
+                    println!("{}: {} panics: {}", path.display(), target.name(), msg);
+                    failures += 1;
+                }
+            }
+        }
+    }
+    println!("{} files replayed", count);
+    if failures > 0 {
+        return Err(io::Error::other(format!("{} crashes", failures)));
+    }
+    Ok(())
+}
+
+fn fuzz(target: Target, dirs: &[PathBuf], crashes: &Path, runs: u64, seed: u64) -> io::Result<()> {
+    let mut corpus = Vec::new();
+    for dir in dirs {
+        corpus.extend(read_dir(dir)?.into_iter().map(|(_, data)| data));
+    }
+    if corpus.is_empty() {
+        corpus.push("99".to_string());
+    }
+
+    let mut covered = HashSet::new();
+    let mut found = HashSet::new();
+    let mut record = |data: &str, msg: String| -> io::Result<()> {
+        // one file per panic message, the first input that hit it
+        if found.insert(msg.clone()) {
+            let path = crashes.join(format!("crash-{}-{}", target.name(), hash_name(data)));
+            fs::write(&path, data)?;
+            println!("crash: {} ({})", msg, path.display());
+        }
+        Ok(())
+    };
+    for data in corpus.clone() {
+        match target.crash(&data) {
+            Ok(features) => covered.extend(features),
+            Err(msg) => record(&data, msg)?,
+        }
+    }
+    println!("seed {}, {} inputs, {} features", seed, corpus.len(), covered.len());
+
+    let mut rng = Rng(seed.max(1));
+    for run in 1..=runs {
+        let parent = &corpus[rng.below(corpus.len())];
+        let data = mutate(&mut rng, parent, &corpus);
+        let features = match target.crash(&data) {
+            Ok(features) => features,
+            Err(msg) => {
+                record(&data, msg)?;
+                continue;
+            }
+        };
+        let before = covered.len();
+        covered.extend(features);
+        if covered.len() > before {
+            // new entries go to the first corpus directory
+            if let Some(dir) = dirs.first() {
+                fs::write(dir.join(hash_name(&data)), &data)?;
+            }
+            corpus.push(data);
+        }
+        if run % 10_000 == 0 {
+            println!("run {}, {} inputs, {} features", run, corpus.len(), covered.len());
+        }
+    }
+    println!(
+        "{} runs, {} inputs, {} features, {} crashes",
+        runs,
+        corpus.len(),
+        covered.len(),
+        found.len()
+    );
+    if !found.is_empty() {
+        return Err(io::Error::other(format!("{} crashes", found.len())));
+    }
+    Ok(())
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut target = None;
+    let mut runs = 100_000;
+    let mut seed = 1;
+    let mut crashes = PathBuf::from(".");
+    let mut replaying = false;
+    let mut dirs = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let number = |s: String| s.parse::<u64>().map_err(|_| invalid(format!("bad number {}", s)));
+        match arg.as_str() {
+            "--target" => {
+                let name = value(&mut iter, arg)?;
+                target = TARGETS.iter().cloned().find(|t| t.name() == name);
+                if target.is_none() {
+                    return Err(invalid(format!("unknown target {}, use parse, decode or execute", name)));
+                }
+            }
+            "--runs" => runs = number(value(&mut iter, arg)?)?,
+            "--seed" => seed = number(value(&mut iter, arg)?)?,
+            "--crashes" => crashes = PathBuf::from(value(&mut iter, arg)?),
+            "--replay" => replaying = true,
+            _ => dirs.push(PathBuf::from(arg)),
+        }
+    }
+
+    // panics are what we are looking for, report them once
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = if replaying {
+        replay(&dirs)
+    } else {
+        match target {
+            Some(target) => fuzz(target, &dirs, &crashes, runs, seed),
+            None => Err(invalid("missing --target".to_string())),
+        }
+    };
+    panic::set_hook(hook);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{ParseError, Problem};
+
+    fn dir(name: &str) -> PathBuf {
+        Path::new(file!()).with_file_name("fuzz").join(name)
+    }
+
+    // how a regression ends, with an input value ready, and the first cell
+    // afterwards
+    fn outcome(name: &str, checked: bool) -> Result<(Result<State, Fault>, isize), ParseError> {
+        let data = fs::read_to_string(dir("regressions").join(name)).unwrap();
+        let mut program = data.parse::<IntCodeProgram>()?;
+        program.checked = checked;
+        program.add_input(1);
+        let state = program.execute();
+        Ok((state, program.code[0]))
+    }
+
+    const FAULTS: &[(&str, Fault)] = &[
+        ("bad-mode.txt", Fault::BadMode { ip: 0, opcode: 301 }),
+        ("bad-opcode.txt", Fault::BadOpcode { ip: 0, opcode: 42 }),
+        ("input-negative-address.txt", Fault::OutOfBounds { ip: 0, addr: -1 }),
+        ("ip-past-end.txt", Fault::OutOfBounds { ip: 4, addr: 4 }),
+        ("jump-negative.txt", Fault::OutOfBounds { ip: 0, addr: -5 }),
+        ("read-out-of-bounds.txt", Fault::OutOfBounds { ip: 0, addr: 100 }),
+        ("truncated-instruction.txt", Fault::OutOfBounds { ip: 0, addr: 2 }),
+        ("write-negative-address.txt", Fault::OutOfBounds { ip: 0, addr: -1 }),
+    ];
+
+    // wrapped result without checks
+    const OVERFLOWS: &[(&str, isize)] = &[("add-overflow.txt", isize::MIN), ("multiply-overflow.txt", -2)];
+
+    const MALFORMED: &[&str] = &["empty.txt", "not-a-number.txt"];
+
+    #[test]
+    fn faults() {
+        for &(name, fault) in FAULTS {
+            for checked in [false, true] {
+                assert_eq!(outcome(name, checked).map(|o| o.0), Ok(Err(fault)), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn overflows() {
+        for &(name, wrapped) in OVERFLOWS {
+            assert_eq!(outcome(name, false), Ok((Ok(State::Halted), wrapped)), "{}", name);
+            assert_eq!(outcome(name, true).map(|o| o.0), Ok(Err(Fault::Overflow { ip: 0 })), "{}", name);
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        let empty = outcome("empty.txt", false).unwrap_err();
+        assert_eq!(
+            empty,
+            ParseError {
+                index: 0,
+                line: 1,
+                column: 1,
+                token: String::new(),
+                problem: Problem::Empty
+            }
+        );
+        let bad = outcome("not-a-number.txt", false).unwrap_err();
+        assert_eq!((bad.index, bad.line, bad.column, bad.token.as_str()), (2, 1, 5, "x"));
+        assert!(matches!(bad.problem, Problem::BadValue(_)), "{:?}", bad.problem);
+    }
+
+    // a new crash file has to get an expected result above
+    #[test]
+    fn all_pinned() {
+        for (path, _) in read_dir(&dir("regressions")).unwrap() {
+            let name = path.file_name().unwrap().to_str().unwrap();
+            let pinned = FAULTS.iter().any(|f| f.0 == name) || OVERFLOWS.iter().any(|o| o.0 == name) || MALFORMED.contains(&name);
+            assert!(pinned, "{} has no expected result", name);
+        }
+    }
+
+    // what `intcode fuzz --replay` checks
+    #[test]
+    fn no_panics() {
+        for name in ["corpus", "regressions"] {
+            for (path, data) in read_dir(&dir(name)).unwrap() {
+                for &target in TARGETS {
+                    assert_eq!(target.crash(&data).err(), None, "{} {}", path.display(), target.name());
+                }
+            }
+        }
+    }
+}
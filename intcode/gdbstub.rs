@@ -0,0 +1,537 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::io;
+use std::io::prelude::*;
+use std::net::{TcpListener, TcpStream};
+
+use crate::host::*;
+use crate::vm::*;
+
+// gdb sees memory as bytes, so every cell is 8 little endian bytes and the
+// pc is the byte address of ip
+const CELL_BYTES: usize = 8;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGFPE: u8 = 8;
+const SIGSEGV: u8 = 11;
+
+// one register, the pc
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.intcode.core">
+    <reg name="pc" bitsize="64" type="code_ptr" regnum="0"/>
+  </feature>
+</target>
+"#;
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| s.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect()
+}
+
+fn parse_hex(s: &str) -> Option<usize> {
+    usize::from_str_radix(s, 16).ok()
+}
+
+// the connection, with bytes read ahead while looking for interrupts
+struct Connection {
+    stream: TcpStream,
+    pending: VecDeque<u8>,
+    no_ack: bool,
+}
+
+impl Connection {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if let Some(b) = self.pending.pop_front() {
+            return Ok(Some(b));
+        }
+        let mut buf = [0; 1];
+        match self.stream.read(&mut buf)? {
+            0 => Ok(None),
+            _ => Ok(Some(buf[0])),
+        }
+    }
+
+    // the next packet, None when the client is gone. a lone 0x03 is an
+    // interrupt, which only matters while running
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => {}
+                Some(_) => continue,
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(b) => data.push(b),
+                }
+            }
+            let mut checksum = [0; 2];
+            for c in checksum.iter_mut() {
+                *c = self.read_byte()?.unwrap_or(0);
+            }
+            let expected = String::from_utf8_lossy(&checksum).to_string();
+            let actual = data.iter().fold(0u8, |acc, &b| acc.wrapping_add(b));
+            if !self.no_ack {
+                if parse_hex(&expected) == Some(actual as usize) {
+                    self.stream.write_all(b"+")?;
+                } else {
+                    self.stream.write_all(b"-")?;
+                    continue;
+                }
+            }
+            return Ok(Some(String::from_utf8_lossy(&data).to_string()));
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let checksum = data.bytes().fold(0u8, |acc, b| acc.wrapping_add(b));
+        self.stream.write_all(format!("${}#{:02x}", data, checksum).as_bytes())?;
+        if self.no_ack {
+            return Ok(());
+        }
+        // wait for the ack, resending on a nack
+        loop {
+            match self.read_byte()? {
+                Some(b'+') | None => return Ok(()),
+                Some(b'-') => {
+                    self.stream.write_all(format!("${}#{:02x}", data, checksum).as_bytes())?;
+                }
+                Some(b) => self.pending.push_back(b),
+            }
+        }
+    }
+
+    // true if the client sent an interrupt, without blocking
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut buf = [0; 256];
+        let result = loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => break Ok(()),
+                Ok(n) => self.pending.extend(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.stream.set_nonblocking(false)?;
+        result?;
+        match self.pending.iter().position(|&b| b == 0x03) {
+            Some(i) => {
+                self.pending.remove(i);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+// why the machine stopped
+enum Stop {
+    Signal(u8),
+    Exited,
+}
+
+struct Stub {
+    program: IntCodeProgram,
+    breakpoints: BTreeSet<usize>,
+}
+
+impl Stub {
+    fn stop_reply(stop: Stop) -> String {
+        match stop {
+            Stop::Signal(signal) => format!("S{:02x}", signal),
+            Stop::Exited => "W00".to_string(),
+        }
+    }
+
+    // sends outputs to the gdb console as they appear
+    fn flush_output(&mut self, conn: &mut Connection) -> io::Result<()> {
+        while let Some(output) = self.program.take_output() {
+            conn.send(&format!("O{}", to_hex(format!("output: {}\n", output).as_bytes())))?;
+        }
+        Ok(())
+    }
+
+    fn step(&mut self, conn: &mut Connection) -> io::Result<Option<Stop>> {
+        let res = match self.program.step() {
+            Ok(State::Running) => None,
+            Ok(State::Halted) => Some(Stop::Exited),
+            Ok(State::WaitingForInput) => {
+                let msg = "program needs input, use monitor input <value>\n";
+                conn.send(&format!("O{}", to_hex(msg.as_bytes())))?;
+                Some(Stop::Signal(SIGTRAP))
+            }
+            Err(fault) => {
+                conn.send(&format!("O{}", to_hex(format!("{}\n", fault).as_bytes())))?;
+                Some(Stop::Signal(match fault {
+                    Fault::BadOpcode { .. } | Fault::BadMode { .. } | Fault::BadExtension { .. } => SIGILL,
+                    Fault::OutOfBounds { .. } => SIGSEGV,
+                    Fault::Overflow { .. } => SIGFPE,
+                }))
+            }
+        };
+        self.flush_output(conn)?;
+        Ok(res)
+    }
+
+    fn resume(&mut self, conn: &mut Connection) -> io::Result<Stop> {
+        // step off a breakpoint we are stopped at
+        if let Some(stop) = self.step(conn)? {
+            return Ok(stop);
+        }
+        let mut steps = 0u64;
+        loop {
+            if self.breakpoints.contains(&self.program.ip) {
+                return Ok(Stop::Signal(SIGTRAP));
+            }
+            if let Some(stop) = self.step(conn)? {
+                return Ok(stop);
+            }
+            steps += 1;
+            if steps.is_multiple_of(10_000) && conn.interrupted()? {
+                return Ok(Stop::Signal(SIGINT));
+            }
+        }
+    }
+
+    fn read_memory(&self, addr: usize, len: usize) -> Option<Vec<u8>> {
+        let bytes = self.program.code.len() * CELL_BYTES;
+        if addr >= bytes {
+            return None;
+        }
+        let end = addr.saturating_add(len).min(bytes);
+        Some(
+            (addr..end)
+                .map(|a| self.program.code[a / CELL_BYTES].to_le_bytes()[a % CELL_BYTES])
+                .collect(),
+        )
+    }
+
+    fn write_memory(&mut self, addr: usize, data: &[u8]) -> bool {
+        if addr.saturating_add(data.len()) > self.program.code.len() * CELL_BYTES {
+            return false;
+        }
+        for (i, &b) in data.iter().enumerate() {
+            let a = addr + i;
+            let cell = &mut self.program.code[a / CELL_BYTES];
+            let mut bytes = cell.to_le_bytes();
+            bytes[a % CELL_BYTES] = b;
+            *cell = isize::from_le_bytes(bytes);
+        }
+        true
+    }
+
+    fn pc(&self) -> String {
+        to_hex(&((self.program.ip * CELL_BYTES) as u64).to_le_bytes())
+    }
+
+    fn set_pc(&mut self, hex: &str) -> bool {
+        match from_hex(hex) {
+            Some(bytes) if bytes.len() == 8 => {
+                let mut value = [0; 8];
+                value.copy_from_slice(&bytes);
+                self.program.ip = u64::from_le_bytes(value) as usize / CELL_BYTES;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // `monitor` commands
+    fn command(&mut self, cmd: &str) -> String {
+        let words = cmd.split_whitespace().collect::<Vec<&str>>();
+        let reply = match words.first() {
+            Some(&"input") => {
+                let values = words[1..].iter().map(|w| w.parse::<isize>()).collect::<Result<Vec<isize>, _>>();
+                match values {
+                    Ok(values) => {
+                        self.program.input.extend(values);
+                        format!("{} inputs queued\n", self.program.input.len())
+                    }
+                    Err(_) => "usage: monitor input <value>...\n".to_string(),
+                }
+            }
+            Some(&"cell") => match words.get(1).and_then(|w| w.parse::<usize>().ok()) {
+                Some(addr) if addr < self.program.code.len() => format!("[{}] = {}\n", addr, self.program.code[addr]),
+                _ => "usage: monitor cell <address>\n".to_string(),
+            },
+            _ => "commands: input <value>..., cell <address>\n".to_string(),
+        };
+        to_hex(reply.as_bytes())
+    }
+
+    // the reply to a packet, None to end the session
+    fn handle(&mut self, packet: &str, conn: &mut Connection) -> io::Result<Option<String>> {
+        let (cmd, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        let reply = match cmd {
+            "?" => "S05".to_string(),
+            "g" => self.pc(),
+            "G" => ok(self.set_pc(args)),
+            "p" => match parse_hex(args) {
+                Some(0) => self.pc(),
+                _ => "E01".to_string(),
+            },
+            "P" => match args.split_once('=') {
+                Some((reg, value)) if parse_hex(reg) == Some(0) => ok(self.set_pc(value)),
+                _ => "E01".to_string(),
+            },
+            "m" => {
+                let read = args
+                    .split_once(',')
+                    .and_then(|(a, l)| Some((parse_hex(a)?, parse_hex(l)?)))
+                    .and_then(|(a, l)| self.read_memory(a, l));
+                read.map_or("E01".to_string(), |bytes| to_hex(&bytes))
+            }
+            "M" => {
+                let write = args.split_once(':').and_then(|(range, data)| {
+                    let (addr, len) = range.split_once(',')?;
+                    let data = from_hex(data)?;
+                    Some((parse_hex(addr)?, parse_hex(len)?, data))
+                });
+                match write {
+                    Some((addr, len, data)) if len == data.len() => ok(self.write_memory(addr, &data)),
+                    _ => "E01".to_string(),
+                }
+            }
+            "s" => {
+                let stop = self.step(conn)?.unwrap_or(Stop::Signal(SIGTRAP));
+                Stub::stop_reply(stop)
+            }
+            "c" => {
+                let stop = self.resume(conn)?;
+                Stub::stop_reply(stop)
+            }
+            // software and hardware breakpoints are the same thing here
+            "Z" | "z" => {
+                let mut parts = args.split(',');
+                let kind = parts.next();
+                match (kind, parts.next().and_then(parse_hex)) {
+                    (Some("0"), Some(addr)) | (Some("1"), Some(addr)) => {
+                        if cmd == "Z" {
+                            self.breakpoints.insert(addr / CELL_BYTES);
+                        } else {
+                            self.breakpoints.remove(&(addr / CELL_BYTES));
+                        }
+                        "OK".to_string()
+                    }
+                    _ => String::new(),
+                }
+            }
+            "H" => "OK".to_string(),
+            "D" => {
+                conn.send("OK")?;
+                return Ok(None);
+            }
+            "k" => return Ok(None),
+            _ => self.query(packet, conn),
+        };
+        Ok(Some(reply))
+    }
+
+    fn query(&mut self, packet: &str, conn: &mut Connection) -> String {
+        if packet.starts_with("qSupported") {
+            "PacketSize=1000;QStartNoAckMode+;qXfer:features:read+".to_string()
+        } else if packet == "QStartNoAckMode" {
+            conn.no_ack = true;
+            "OK".to_string()
+        } else if let Some(rest) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            // offset,length; 'm' means there is more, 'l' that this is the last part
+            let range = rest.split_once(',').and_then(|(o, l)| Some((parse_hex(o)?, parse_hex(l)?)));
+            match range {
+                Some((offset, len)) if offset <= TARGET_XML.len() => {
+                    let end = offset.saturating_add(len).min(TARGET_XML.len());
+                    let more = if end < TARGET_XML.len() { "m" } else { "l" };
+                    format!("{}{}", more, &TARGET_XML[offset..end])
+                }
+                _ => "E01".to_string(),
+            }
+        } else if let Some(hex) = packet.strip_prefix("qRcmd,") {
+            let cmd = from_hex(hex).map(|b| String::from_utf8_lossy(&b).to_string()).unwrap_or_default();
+            self.command(&cmd)
+        } else {
+            match packet {
+                "qAttached" => "1".to_string(),
+                "qC" => "QC1".to_string(),
+                "qfThreadInfo" => "m1".to_string(),
+                "qsThreadInfo" => "l".to_string(),
+                // anything else is unsupported, which is an empty reply
+                _ => String::new(),
+            }
+        }
+    }
+}
+
+fn ok(success: bool) -> String {
+    if success {
+        "OK".to_string()
+    } else {
+        "E01".to_string()
+    }
+}
+
+fn session(program: IntCodeProgram, stream: TcpStream) -> io::Result<()> {
+    // acks and packets are tiny, waiting to batch them only adds latency
+    stream.set_nodelay(true)?;
+    let mut conn = Connection {
+        stream,
+        pending: VecDeque::new(),
+        no_ack: false,
+    };
+    let mut stub = Stub {
+        program,
+        breakpoints: BTreeSet::new(),
+    };
+    while let Some(packet) = conn.read_packet()? {
+        match stub.handle(&packet, &mut conn)? {
+            Some(reply) => conn.send(&reply)?,
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut port = None;
+    let mut inputs = Vec::new();
+    let mut filename = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--port" => {
+                let value = value(&mut iter, arg)?;
+                port = Some(value.parse::<u16>().map_err(|_| invalid(format!("bad port {}", value)))?);
+            }
+            "--input" => {
+                let value = value(&mut iter, arg)?;
+                inputs.push(value.parse::<isize>().map_err(|_| invalid(format!("bad number {}", value)))?);
+            }
+            _ => filename = Some(arg.as_str()),
+        }
+    }
+
+    let port = port.ok_or_else(|| invalid("missing --port".to_string()))?;
+    let mut program = load_program(filename)?;
+    program.input = inputs;
+
+    // one debugger at a time, each session starts from the loaded program
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!("waiting for gdb on {}, use: target remote {}", listener.local_addr()?, listener.local_addr()?);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("accept: {}", e);
+                continue;
+            }
+        };
+        let peer = stream.peer_addr().map_or_else(|_| "unknown".to_string(), |a| a.to_string());
+        eprintln!("{}: attached", peer);
+        match session(program.clone(), stream) {
+            Ok(()) => eprintln!("{}: detached", peer),
+            Err(e) => eprintln!("{}: {}", peer, e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    // stores 2 + 3 in cell 11 and outputs it
+    const SUM: &str = "1101,2,3,11,4,11,99,0,0,0,0,0";
+
+    // gdb's side of the protocol, with acks
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        fn send(&mut self, data: &str) {
+            let checksum = data.bytes().fold(0u8, |acc, b| acc.wrapping_add(b));
+            self.stream.write_all(format!("${}#{:02x}", data, checksum).as_bytes()).unwrap();
+        }
+
+        // the next packet, skipping the stub's acks
+        fn recv(&mut self) -> String {
+            let mut byte = [0; 1];
+            let mut next = || {
+                self.stream.read_exact(&mut byte).unwrap();
+                byte[0]
+            };
+            while next() != b'$' {}
+            let mut data = Vec::new();
+            loop {
+                match next() {
+                    b'#' => break,
+                    b => data.push(b),
+                }
+            }
+            let checksum = [next(), next()];
+            let actual = data.iter().fold(0u8, |acc, &b| acc.wrapping_add(b));
+            assert_eq!(parse_hex(std::str::from_utf8(&checksum).unwrap()), Some(actual as usize));
+            self.stream.write_all(b"+").unwrap();
+            String::from_utf8(data).unwrap()
+        }
+
+        fn request(&mut self, data: &str) -> String {
+            self.send(data);
+            self.recv()
+        }
+    }
+
+    #[test]
+    fn scripted_session() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let program = SUM.parse::<IntCodeProgram>().unwrap();
+        let stub = thread::spawn(move || session(program, listener.accept()?.0));
+        let stream = TcpStream::connect(addr).unwrap();
+        // the packets are tiny, don't wait to batch them
+        stream.set_nodelay(true).unwrap();
+        let mut gdb = Client { stream };
+
+        assert_eq!(gdb.request("qSupported:swbreak+"), "PacketSize=1000;QStartNoAckMode+;qXfer:features:read+");
+        assert_eq!(gdb.request("qXfer:features:read:target.xml:0,ffffffffffffffff"), format!("l{}", TARGET_XML));
+        assert_eq!(gdb.request("qXfer:features:read:target.xml:1,ffffffffffffffff"), format!("l{}", &TARGET_XML[1..]));
+        // unsupported, not a panic on the first character
+        assert_eq!(gdb.request("\u{e9}0"), "");
+        assert_eq!(gdb.request("g"), "0000000000000000");
+        // 1101 at address 0
+        assert_eq!(gdb.request("m0,8"), "4d04000000000000");
+
+        // break on the output instruction at cell 4
+        assert_eq!(gdb.request("Z0,20,1"), "OK");
+        assert_eq!(gdb.request("c"), "S05");
+        assert_eq!(gdb.request("g"), "2000000000000000");
+        assert_eq!(gdb.request("m58,8"), "0500000000000000");
+        assert_eq!(gdb.request("z0,20,1"), "OK");
+
+        // the output goes to the console before the stop reply
+        assert_eq!(gdb.request("s"), format!("O{}", to_hex(b"output: 5\n")));
+        assert_eq!(gdb.recv(), "S05");
+        assert_eq!(gdb.request("g"), "3000000000000000");
+        assert_eq!(gdb.request("c"), "W00");
+
+        gdb.send("k");
+        stub.join().unwrap().unwrap();
+    }
+}
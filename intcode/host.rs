@@ -0,0 +1,58 @@
+// the parts that need an operating system: loading programs from files
+// and stdin
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+
+use crate::binary::{self, FormatError};
+use crate::cell::Cell;
+use crate::vm::*;
+
+// reads a program from the given file, text or binary, or from stdin up to
+// a blank line or the end, since the input values may come after it
+pub fn load_program(filename: Option<&str>) -> io::Result<IntCodeProgram> {
+    load_cells(filename)
+}
+
+// like load_program, for any cell type
+pub fn load_cells<C: Cell>(filename: Option<&str>) -> io::Result<IntCodeProgram<C>>
+where
+    C::Err: Error + Send + Sync + 'static,
+{
+    let mut text = String::new();
+    if let Some(filename) = filename {
+        let mut bytes = Vec::new();
+        File::open(filename)?.read_to_end(&mut bytes)?;
+        if binary::is_binary(&bytes) {
+            return Ok(IntCodeProgram::new(binary::read_cells(&bytes)?));
+        }
+        text = String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    } else {
+        let stdin = io::stdin();
+        let mut stdin = stdin.lock();
+        let mut line = String::new();
+        while stdin.read_line(&mut line)? > 0 && !line.trim().is_empty() {
+            text.push_str(&line);
+            line.clear();
+        }
+    }
+
+    text.parse::<IntCodeProgram<C>>().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// a bad command line argument
+pub fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+// the value after an option like `--max-steps 100`
+pub fn value<'a>(iter: &mut impl Iterator<Item = &'a String>, arg: &str) -> io::Result<String> {
+    iter.next().cloned().ok_or_else(|| invalid(format!("{} needs a value", arg)))
+}
+
+impl From<FormatError> for io::Error {
+    fn from(e: FormatError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
@@ -0,0 +1,30 @@
+// the machine as a library. the core only needs alloc; loading programs
+// from files, console I/O, the async channels and the C interface in capi.rs
+// need std. std is on unless the no_std feature is set:
+//   rustc --edition 2018 --crate-type cdylib --crate-name intcode lib.rs
+// and for no_std targets:
+//   rustc --edition 2018 --crate-type rlib --crate-name intcode --cfg 'feature="no_std"' lib.rs
+#![cfg_attr(feature = "no_std", no_std)]
+
+extern crate alloc;
+
+pub mod asyncio;
+pub mod bigint;
+pub mod binary;
+#[cfg(not(feature = "no_std"))]
+pub mod capi;
+pub mod cell;
+#[cfg(not(feature = "no_std"))]
+pub mod channel;
+#[cfg(not(feature = "no_std"))]
+pub mod console;
+pub mod coverage;
+pub mod decode;
+pub mod executor;
+#[cfg(not(feature = "no_std"))]
+pub mod host;
+pub mod outputs;
+pub mod parse;
+pub mod ports;
+pub mod registry;
+pub mod vm;
@@ -0,0 +1,156 @@
+// the machine itself only needs alloc, see lib.rs
+extern crate alloc;
+
+use std::env;
+use std::error::Error;
+use std::io;
+use std::io::prelude::*;
+
+mod ascii;
+mod batch;
+mod bigint;
+mod binary;
+mod cell;
+mod compile;
+mod console;
+mod convert;
+mod cover;
+mod coverage;
+mod decode;
+mod decompile;
+mod diagnose;
+mod difftest;
+mod dump;
+mod fuzz;
+mod gdbstub;
+mod host;
+mod optimize;
+mod parse;
+mod ports;
+mod registry;
+mod search;
+mod serve;
+mod session;
+mod symbolic;
+mod visualize;
+mod vm;
+
+use ascii::*;
+use bigint::BigInt;
+use cell::Cell;
+use console::*;
+use host::*;
+use session::{fingerprint, Options, Recorder};
+use vm::*;
+
+// runs a program like day5 does, reading input values from stdin, or as
+// text with --ascii
+fn run(args: &[String]) -> io::Result<()> {
+    let mut options = Options::default();
+    let mut ascii = false;
+    let mut record = None;
+    let mut filename = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--cells" => {
+                options.cells = value(&mut iter, arg)?;
+            }
+            "--checked" => options.checked = true,
+            "--ascii" => ascii = true,
+            // the sample extension opcodes from registry.rs
+            "--extended" => options.extended = true,
+            "--strict" => options.strict = true,
+            // logs inputs and outputs for `intcode replay`
+            "--record" => {
+                record = Some(value(&mut iter, arg)?);
+            }
+            _ => filename = Some(arg.as_str()),
+        }
+    }
+
+    let mut recorder = record.as_ref().map(|_| Recorder::new(&options));
+    let result = match options.cells.as_str() {
+        "isize" => run_cells::<isize>(filename, &options, recorder.as_mut(), ascii),
+        "i64" => run_cells::<i64>(filename, &options, recorder.as_mut(), ascii),
+        "i128" => run_cells::<i128>(filename, &options, recorder.as_mut(), ascii),
+        "big" => run_cells::<BigInt>(filename, &options, recorder.as_mut(), ascii),
+        _ => return Err(invalid(format!("unknown cell type {}, use isize, i64, i128 or big", options.cells))),
+    };
+    // keep the session even if the run failed, that's when it's needed most
+    if let (Some(record), Some(recorder)) = (record, recorder) {
+        recorder.save(&record)?;
+    }
+    result
+}
+
+fn run_cells<C: Cell + 'static>(filename: Option<&str>, options: &Options, mut recorder: Option<&mut Recorder>, ascii: bool) -> io::Result<()>
+where
+    C::Err: Error + Send + Sync + 'static,
+{
+    let mut program = load_cells::<C>(filename)?;
+    options.apply(&mut program);
+    if let Some(recorder) = recorder.as_deref_mut() {
+        recorder.program = Some(fingerprint(&program.code));
+    }
+    let state = if ascii {
+        run_ports(&mut program, &mut AsciiInput::new(), &mut AsciiOutput::new(), recorder.as_deref_mut())
+    } else {
+        run_ports(&mut program, &mut StdinValues::new(), &mut StdoutValues, recorder.as_deref_mut())
+    };
+    io::stdout().flush()?;
+    if state.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))? == State::WaitingForInput {
+        if let Some(recorder) = recorder {
+            recorder.end("needs input");
+        }
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "program needs more input"));
+    }
+    Ok(())
+}
+
+// the recorder steps the machine itself to count the steps, which is only
+// worth it when the session is saved
+fn run_ports<C: Cell>(
+    program: &mut IntCodeProgram<C>,
+    input: &mut impl ports::Input<C>,
+    output: &mut impl ports::Output<C>,
+    recorder: Option<&mut Recorder>,
+) -> Result<State, Fault> {
+    match recorder {
+        Some(recorder) => ports::run_with(program, input, output, |program| recorder.execute(program)),
+        None => ports::run(program, input, output),
+    }
+}
+
+fn main() -> io::Result<()> {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    let command = args.first().map(String::as_str).unwrap_or_default();
+    let args = args.get(1..).unwrap_or_default();
+
+    match command {
+        "run" => run(args),
+        "optimize" => optimize::run(args),
+        "decompile" => decompile::run(args),
+        "compile" => compile::run(args),
+        "symbolic" => symbolic::run(args),
+        "search" => search::run(args),
+        "difftest" => difftest::run(args),
+        "fuzz" => fuzz::run(args),
+        "serve" => serve::run(args),
+        "gdb" => gdbstub::run(args),
+        "visualize" => visualize::run(args),
+        "replay" => session::run(args),
+        "batch" => batch::run(args),
+        "diagnose" => diagnose::run(args),
+        "convert" => convert::run(args),
+        "coverage" => cover::run(args),
+        "dump" => dump::run(args),
+        _ => {
+            eprintln!("usage: intcode <command> [options] [program]");
+            eprintln!("without a program file it is read from stdin up to a blank line, input values may follow it");
+            eprintln!("commands: run, optimize, decompile, compile, symbolic, search, difftest, fuzz, serve, gdb, visualize, replay, batch, diagnose, convert, coverage, dump");
+            Err(invalid("unknown command".to_string()))
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+
+use crate::decode::*;
+use crate::host::*;
+use crate::vm::*;
+
+pub struct Change {
+    pub addr: usize,
+    pub rule: &'static str,
+    pub before: Instruction,
+    pub after: Option<Instruction>,
+}
+
+pub struct Optimized {
+    pub code: Vec<isize>,
+    pub changes: Vec<Change>,
+    pub notes: Vec<String>,
+}
+
+fn constant_result(instr: &Instruction) -> Option<isize> {
+    match (instr.params.first()?, instr.params.get(1)?) {
+        (&Param::Immediate(a), &Param::Immediate(b)) => match instr.opcode {
+            OPCODE_ADD => a.checked_add(b),
+            OPCODE_MULT => a.checked_mul(b),
+            OPCODE_LESS_THAN => Some((a < b) as isize),
+            OPCODE_EQUALS => Some((a == b) as isize),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn load_constant(addr: usize, value: isize, out: Param) -> Instruction {
+    Instruction::new(addr, OPCODE_ADD, vec![Param::Immediate(value), Param::Immediate(0), out])
+}
+
+fn is_load_constant(instr: &Instruction) -> bool {
+    instr.opcode == OPCODE_ADD
+        && matches!(instr.params[0], Param::Immediate(_))
+        && instr.params[1] == Param::Immediate(0)
+}
+
+// rewrites that keep the instruction length
+fn simplify(instr: &Instruction) -> Option<(&'static str, Instruction)> {
+    if let Some(value) = constant_result(instr) {
+        if is_load_constant(instr) {
+            return None;
+        }
+        return Some(("constant-fold", load_constant(instr.addr, value, instr.params[2])));
+    }
+    if instr.opcode != OPCODE_MULT {
+        return None;
+    }
+    for &(factor, other) in &[(0, 1), (1, 0)] {
+        match instr.params[factor] {
+            Param::Immediate(1) => {
+                let params = vec![instr.params[other], Param::Immediate(0), instr.params[2]];
+                return Some(("multiply-by-one", Instruction::new(instr.addr, OPCODE_ADD, params)));
+            }
+            Param::Immediate(0) => {
+                return Some(("multiply-by-zero", load_constant(instr.addr, 0, instr.params[2])));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// `EQUALS x, 0, [c]` followed by a jump on [c] is a jump on x with the
+// opposite condition
+fn zero_test(instr: &Instruction) -> Option<Param> {
+    if instr.opcode != OPCODE_EQUALS {
+        return None;
+    }
+    match (instr.params[0], instr.params[1]) {
+        (Param::Immediate(0), x) | (x, Param::Immediate(0)) => Some(x),
+        _ => None,
+    }
+}
+
+// instructions that have no effect and can be dropped when relocating
+fn removable(instr: &Instruction) -> Option<&'static str> {
+    if instr.opcode == OPCODE_ADD {
+        let out = Param::Position(instr.params[2].value());
+        let identity = (instr.params[0] == out && instr.params[1] == Param::Immediate(0))
+            || (instr.params[0] == Param::Immediate(0) && instr.params[1] == out);
+        if identity {
+            return Some("identity");
+        }
+    }
+    if instr.is_jump() {
+        if instr.params[1] == Param::Immediate(instr.next() as isize) {
+            return Some("jump-to-next");
+        }
+        if let Param::Immediate(c) = instr.params[0] {
+            if (c != 0) != (instr.opcode == OPCODE_JUMP_IF_TRUE) {
+                return Some("never-taken");
+            }
+        }
+    }
+    None
+}
+
+fn remap(addr: isize, new_addr: &[usize]) -> isize {
+    if addr < 0 {
+        addr
+    } else if (addr as usize) < new_addr.len() {
+        new_addr[addr as usize] as isize
+    } else {
+        // past the end of the program, keep the same distance to the end
+        addr - new_addr.len() as isize + *new_addr.last().unwrap_or(&0) as isize
+    }
+}
+
+// cells in keep hold results, like day2's answer in cell 0, so rewrites
+// must leave their final values alone
+pub fn optimize(code: &[isize], relocate: bool, keep: &BTreeSet<usize>) -> Optimized {
+    let flow = Flow::new(code);
+    let mut res = Optimized {
+        code: code.to_vec(),
+        changes: Vec::new(),
+        notes: Vec::new(),
+    };
+
+    if flow.unknown_writes {
+        res.notes.push("program writes through computed addresses, no region is provably free of self-modification".to_string());
+        return res;
+    }
+    if flow.dynamic_jumps {
+        res.notes.push("program jumps to computed addresses, control flow is not fully known".to_string());
+        return res;
+    }
+
+    let mut reads = BTreeMap::new();
+    for instr in flow.instructions.values() {
+        for (i, p) in instr.params.iter().enumerate() {
+            if let Param::Position(a) = *p {
+                if Some(i) == instr.write_param() {
+                    continue;
+                }
+                if flow.is_written(instr.addr + 1 + i) {
+                    res.notes.push(format!("{}: reads through a computed address", instr.addr));
+                    return res;
+                }
+                *reads.entry(a).or_insert(0) += 1;
+            }
+        }
+    }
+
+    // only instructions that are neither written nor read as data are touched
+    let mut instructions = BTreeMap::new();
+    let mut skipped = 0;
+    for instr in flow.instructions.values() {
+        let as_data = (instr.addr..instr.next()).any(|a| reads.contains_key(&(a as isize)));
+        if flow.is_clean(instr) && !as_data {
+            instructions.insert(instr.addr, instr.clone());
+        } else {
+            skipped += 1;
+        }
+    }
+    if skipped > 0 {
+        res.notes.push(format!("{} instructions skipped because the program modifies or reads them", skipped));
+    }
+
+    let mut relocate = relocate;
+    if relocate && skipped > 0 {
+        res.notes.push("not relocating, the program is not free of self-modification".to_string());
+        relocate = false;
+    }
+    if relocate && flow.instructions.values().any(|i| i.is_jump() && matches!(i.params[1], Param::Position(_))) {
+        res.notes.push("not relocating, some jump targets are stored in memory".to_string());
+        relocate = false;
+    }
+
+    for instr in instructions.values_mut() {
+        if let Some((rule, after)) = simplify(instr) {
+            res.changes.push(Change { addr: instr.addr, rule, before: instr.clone(), after: Some(after.clone()) });
+            *instr = after;
+        }
+    }
+
+    // pairs of an instruction writing [c] and a jump directly behind it
+    // that tests [c], as long as nothing else can jump in between
+    let mut removed = BTreeSet::new();
+    let addrs = instructions.keys().cloned().collect::<Vec<usize>>();
+    for addr in addrs {
+        let first = instructions[&addr].clone();
+        let jump = match instructions.get(&first.next()) {
+            Some(jump) if jump.is_jump() && !flow.jump_targets.contains(&jump.addr) => jump.clone(),
+            _ => continue,
+        };
+        if first.write_target().map(Param::Position) != Some(jump.params[0]) {
+            continue;
+        }
+        if let Some(value) = constant_result(&first) {
+            let mut after = jump.clone();
+            after.params[0] = Param::Immediate(value);
+            res.changes.push(Change { addr: jump.addr, rule: "branch-fold", before: jump, after: Some(after.clone()) });
+            instructions.insert(after.addr, after);
+        } else if let Some(x) = zero_test(&first) {
+            // the compare's write to [c] goes away, so nothing else may read
+            // c and its final value must not matter
+            let c = jump.params[0].value();
+            if !relocate || reads.get(&c) != Some(&1) || keep.contains(&(c as usize)) {
+                continue;
+            }
+            let opcode = if jump.opcode == OPCODE_JUMP_IF_TRUE { OPCODE_JUMP_IF_FALSE } else { OPCODE_JUMP_IF_TRUE };
+            let after = Instruction::new(addr, opcode, vec![x, jump.params[1]]);
+            res.changes.push(Change { addr, rule: "zero-test", before: first, after: Some(after.clone()) });
+            res.changes.push(Change { addr: jump.addr, rule: "zero-test", before: jump.clone(), after: None });
+            instructions.insert(addr, after);
+            removed.insert(jump.addr);
+        }
+    }
+
+    if !relocate {
+        for instr in instructions.values() {
+            res.code[instr.addr..instr.next()].copy_from_slice(&instr.encode());
+        }
+        res.changes.sort_by_key(|c| c.addr);
+        return res;
+    }
+
+    for instr in instructions.values() {
+        if removed.contains(&instr.addr) {
+            continue;
+        }
+        if let Some(rule) = removable(instr) {
+            res.changes.push(Change { addr: instr.addr, rule, before: instr.clone(), after: None });
+            removed.insert(instr.addr);
+        }
+    }
+
+    // compute the new address of every old cell; cells of removed
+    // instructions map to whatever follows them
+    let mut new_addr = Vec::with_capacity(code.len() + 1);
+    let mut pos = 0;
+    let mut addr = 0;
+    while addr < code.len() {
+        if let Some(instr) = instructions.get(&addr) {
+            let old_len = flow.instructions[&addr].len();
+            new_addr.extend(std::iter::repeat_n(pos, old_len));
+            if !removed.contains(&addr) {
+                pos += instr.len();
+            }
+            addr += old_len;
+        } else {
+            new_addr.push(pos);
+            pos += 1;
+            addr += 1;
+        }
+    }
+    new_addr.push(pos);
+
+    let mut out = Vec::with_capacity(pos);
+    let mut addr = 0;
+    while addr < code.len() {
+        if let Some(instr) = instructions.get(&addr) {
+            if !removed.contains(&addr) {
+                let mut instr = instr.clone();
+                let is_jump = instr.is_jump();
+                for (i, p) in instr.params.iter_mut().enumerate() {
+                    *p = match *p {
+                        Param::Position(a) => Param::Position(remap(a, &new_addr)),
+                        Param::Immediate(t) if is_jump && i == 1 => Param::Immediate(remap(t, &new_addr)),
+                        other => other,
+                    };
+                }
+                out.extend(instr.encode());
+            }
+            addr += flow.instructions[&addr].len();
+        } else {
+            out.push(code[addr]);
+            addr += 1;
+        }
+    }
+
+    res.code = out;
+    res.changes.sort_by_key(|c| c.addr);
+    res
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut relocate = false;
+    let mut keep = BTreeSet::from([0]);
+    let mut filename = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--relocate" => relocate = true,
+            // a cell whose final value matters besides cell 0
+            "--keep" => {
+                let value = value(&mut iter, arg)?;
+                keep.insert(value.parse::<usize>().map_err(|_| invalid(format!("bad address {}", value)))?);
+            }
+            _ => filename = Some(arg.as_str()),
+        }
+    }
+    let program = load_program(filename)?;
+
+    let res = optimize(&program.code, relocate, &keep);
+    for note in &res.notes {
+        eprintln!("note: {}", note);
+    }
+    for change in &res.changes {
+        match &change.after {
+            Some(after) => eprintln!("{:>5}: {}: {} => {}", change.addr, change.rule, change.before, after),
+            None => eprintln!("{:>5}: {}: {} => removed", change.addr, change.rule, change.before),
+        }
+    }
+    eprintln!("{} changes, {} -> {} cells", res.changes.len(), program.code.len(), res.code.len());
+    println!("{}", format_code(&res.code));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the outputs and final memory
+    fn execute(code: &[isize], inputs: &[isize]) -> (Vec<isize>, Vec<isize>) {
+        let mut program = IntCodeProgram::new(code.to_vec());
+        program.input = inputs.to_vec();
+        assert_eq!(program.execute(), Ok(State::Halted));
+        (program.output, program.code)
+    }
+
+    fn rules(res: &Optimized) -> Vec<&'static str> {
+        res.changes.iter().map(|c| c.rule).collect()
+    }
+
+    // [21] = input == 0, then output 0 if it was and 1 otherwise
+    const ZERO_TEST: &[isize] = &[3, 20, 1008, 20, 0, 21, 1005, 21, 12, 104, 1, 99, 104, 0, 99, 0, 0, 0, 0, 0, 0, 0];
+
+    #[test]
+    fn constant_fold() {
+        let code = [1101, 2, 3, 5, 99, 0];
+        let res = optimize(&code, false, &BTreeSet::new());
+        assert_eq!(rules(&res), ["constant-fold"]);
+        assert_eq!(res.code, [1101, 5, 0, 5, 99, 0]);
+        assert_eq!(execute(&res.code, &[]).1[5], execute(&code, &[]).1[5]);
+    }
+
+    #[test]
+    fn zero_test() {
+        let res = optimize(ZERO_TEST, true, &BTreeSet::from([0]));
+        assert_eq!(rules(&res), ["zero-test", "zero-test"]);
+        assert_eq!(res.code, [3, 16, 1006, 16, 8, 104, 1, 99, 104, 0, 99, 0, 0, 0, 0, 0, 0, 0]);
+        for input in [0, 5] {
+            assert_eq!(execute(&res.code, &[input]).0, execute(ZERO_TEST, &[input]).0);
+        }
+    }
+
+    // the compare's result is kept, so the rewrite that drops it isn't done
+    #[test]
+    fn zero_test_keeps_result() {
+        let res = optimize(ZERO_TEST, true, &BTreeSet::from([0, 21]));
+        assert!(!rules(&res).contains(&"zero-test"), "{:?}", rules(&res));
+        for input in [0, 5] {
+            let (before, after) = (execute(ZERO_TEST, &[input]), execute(&res.code, &[input]));
+            assert_eq!(after.0, before.0);
+            assert_eq!(after.1[res.code.len() - 1], before.1[21]);
+        }
+    }
+
+    #[test]
+    fn unchanged_without_rules() {
+        let code = [1, 0, 0, 0, 99];
+        let res = optimize(&code, true, &BTreeSet::from([0]));
+        assert!(res.changes.is_empty());
+        assert_eq!(res.code, code);
+    }
+}
@@ -0,0 +1,157 @@
+// machines as iterators over their outputs, see ports.rs for pushing
+// values through a machine instead
+use crate::cell::Cell;
+use crate::vm::*;
+
+// a machine's outputs as an iterator. the machine only runs as far as the
+// next output, taking values from inputs when it needs one, so machines
+// chain like any other iterators: b.outputs(a.outputs(inputs).chain(..)).
+// it stops at a halt or fault, and when the inputs run dry; next() then
+// tries them again, which is enough for feedback loops through a shared
+// queue
+pub struct Outputs<C, I> {
+    program: IntCodeProgram<C>,
+    inputs: I,
+    state: Result<State, Fault>,
+}
+
+impl<C: Cell> IntCodeProgram<C> {
+    pub fn outputs<I: IntoIterator<Item = C>>(self, inputs: I) -> Outputs<C, I::IntoIter> {
+        Outputs {
+            program: self,
+            inputs: inputs.into_iter(),
+            state: Ok(State::Running),
+        }
+    }
+}
+
+impl<C, I> Outputs<C, I> {
+    // why the last call to next() returned None, Running before that
+    pub fn state(&self) -> Result<State, Fault> {
+        self.state
+    }
+
+    pub fn program(&self) -> &IntCodeProgram<C> {
+        &self.program
+    }
+
+    pub fn into_program(self) -> IntCodeProgram<C> {
+        self.program
+    }
+}
+
+impl<C: Cell, I: Iterator<Item = C>> Iterator for Outputs<C, I> {
+    type Item = C;
+
+    fn next(&mut self) -> Option<C> {
+        // outputs the machine had before it was turned into an iterator
+        if let Some(value) = self.program.take_output() {
+            return Some(value);
+        }
+        if matches!(self.state, Ok(State::Halted) | Err(_)) {
+            return None;
+        }
+        loop {
+            match self.program.step() {
+                Ok(State::Running) => {
+                    if let Some(value) = self.program.take_output() {
+                        return Some(value);
+                    }
+                }
+                Ok(State::WaitingForInput) => match self.inputs.next() {
+                    Some(value) => self.program.add_input(value),
+                    None => {
+                        self.state = Ok(State::WaitingForInput);
+                        return None;
+                    }
+                },
+                state => {
+                    self.state = state;
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use alloc::collections::VecDeque;
+    use alloc::rc::Rc;
+    use alloc::vec::Vec;
+    use core::cell::RefCell;
+    use core::iter;
+
+    use super::*;
+
+    fn program(text: &str) -> IntCodeProgram<i64> {
+        text.trim().parse().unwrap()
+    }
+
+    // day7 part 1: each amplifier takes its phase, then the previous one's
+    // output
+    fn chain(code: &IntCodeProgram<i64>, phases: &[i64]) -> Option<i64> {
+        let mut signal: Box<dyn Iterator<Item = i64>> = Box::new(iter::once(0));
+        for &phase in phases {
+            signal = Box::new(code.clone().outputs(iter::once(phase).chain(signal)));
+        }
+        signal.next()
+    }
+
+    #[test]
+    fn day7_chain() {
+        let code = program(include_str!("fuzz/corpus/day7-example1.txt"));
+        assert_eq!(chain(&code, &[4, 3, 2, 1, 0]), Some(43210));
+        assert_eq!(chain(&code, &[0, 1, 2, 3, 4]), Some(1234));
+    }
+
+    // day7 part 2: the amplifiers in a ring, each reading from its own queue,
+    // until they all halt. the last one's last output is the signal
+    fn feedback_loop(code: &IntCodeProgram<i64>, phases: &[i64]) -> Option<i64> {
+        let queues = phases.iter().map(|&p| Rc::new(RefCell::new(VecDeque::from([p])))).collect::<Vec<_>>();
+        queues[0].borrow_mut().push_back(0);
+        let mut amps = queues
+            .iter()
+            .map(|q| {
+                let q = q.clone();
+                code.clone().outputs(iter::from_fn(move || q.borrow_mut().pop_front()))
+            })
+            .collect::<Vec<_>>();
+        let mut last = None;
+        while amps.iter().any(|a| a.state() != Ok(State::Halted)) {
+            let mut progress = false;
+            for (i, amp) in amps.iter_mut().enumerate() {
+                for value in amp.by_ref() {
+                    progress = true;
+                    queues[(i + 1) % queues.len()].borrow_mut().push_back(value);
+                    if i + 1 == queues.len() {
+                        last = Some(value);
+                    }
+                }
+            }
+            assert!(progress, "the amplifiers wait for each other");
+        }
+        last
+    }
+
+    #[test]
+    fn day7_feedback_loop() {
+        let code = program(include_str!("fuzz/corpus/day7-example4.txt"));
+        assert_eq!(feedback_loop(&code, &[9, 8, 7, 6, 5]), Some(139629729));
+    }
+
+    #[test]
+    fn stops() {
+        // waits for a second input that never comes
+        let mut echo = program("3,0,4,0,3,0,4,0,99").outputs(vec![5]);
+        assert_eq!(echo.next(), Some(5));
+        assert_eq!(echo.next(), None);
+        assert_eq!(echo.state(), Ok(State::WaitingForInput));
+
+        let mut faulty = program("104,1,1,100,0,0,99").outputs(iter::empty());
+        assert_eq!(faulty.by_ref().collect::<Vec<i64>>(), [1]);
+        assert!(faulty.state().is_err());
+        assert_eq!(faulty.next(), None);
+    }
+}
@@ -0,0 +1,150 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+
+use crate::cell::Cell;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Problem {
+    Empty,
+    // two commas in a row, or one before the first value
+    MissingValue,
+    // two values on one line without a comma between them
+    MissingComma,
+    // what the cell type said about it
+    BadValue(String),
+}
+
+// where parsing stopped: the index the cell would have had, the 1-based line
+// and column and the offending token
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseError {
+    pub index: usize,
+    pub line: usize,
+    pub column: usize,
+    pub token: String,
+    pub problem: Problem,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}, cell {}: ", self.line, self.column, self.index)?;
+        match &self.problem {
+            Problem::Empty => write!(f, "no cells"),
+            Problem::MissingValue => write!(f, "missing value before {}", self.token),
+            Problem::MissingComma => write!(f, "missing comma before {}", self.token),
+            Problem::BadValue(e) => write!(f, "bad value {}: {}", self.token, e),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+// comma separated cells. whitespace and line breaks may go anywhere between
+// values, and a line break works as a comma. `#` starts a comment that runs
+// to the end of the line, and a trailing comma is fine
+pub fn parse_cells<C: Cell>(text: &str) -> Result<Vec<C>, ParseError>
+where
+    C::Err: fmt::Display,
+{
+    let mut code = Vec::new();
+    // the line of the last value, and whether a comma came after it
+    let mut value_line = None;
+    let mut comma = false;
+    let mut lines = 0;
+
+    for (l, line) in text.lines().enumerate() {
+        lines = l + 1;
+        let line = line.split('#').next().unwrap_or_default();
+        let chars = line.chars().collect::<Vec<char>>();
+        let mut i = 0;
+        while i < chars.len() {
+            let start = i;
+            let error = |token: String, problem| ParseError {
+                index: code.len(),
+                line: l + 1,
+                column: start + 1,
+                token,
+                problem,
+            };
+            if chars[i].is_whitespace() {
+                i += 1;
+            } else if chars[i] == ',' {
+                if value_line.is_none() || comma {
+                    return Err(error(",".to_string(), Problem::MissingValue));
+                }
+                comma = true;
+                i += 1;
+            } else {
+                while i < chars.len() && !chars[i].is_whitespace() && chars[i] != ',' {
+                    i += 1;
+                }
+                let token = chars[start..i].iter().collect::<String>();
+                if value_line == Some(l) && !comma {
+                    return Err(error(token, Problem::MissingComma));
+                }
+                match token.parse::<C>() {
+                    Ok(value) => code.push(value),
+                    Err(e) => return Err(error(token, Problem::BadValue(e.to_string()))),
+                }
+                value_line = Some(l);
+                comma = false;
+            }
+        }
+    }
+
+    if code.is_empty() {
+        return Err(ParseError {
+            index: 0,
+            line: lines.max(1),
+            column: 1,
+            token: String::new(),
+            problem: Problem::Empty,
+        });
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    // line, column, cell index and token of the error
+    fn position(text: &str) -> (usize, usize, usize, String, Problem) {
+        let e = parse_cells::<i64>(text).unwrap_err();
+        (e.line, e.column, e.index, e.token, e.problem)
+    }
+
+    #[test]
+    fn accepted() {
+        assert_eq!(parse_cells::<i64>("1,2,3"), Ok(vec![1, 2, 3]));
+        assert_eq!(parse_cells::<i64>(" 1 ,\n2\n\n3, # the end\n"), Ok(vec![1, 2, 3]));
+        assert_eq!(parse_cells::<i64>("# header\n-1,\n+2,"), Ok(vec![-1, 2]));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(position(""), (1, 1, 0, String::new(), Problem::Empty));
+        assert_eq!(position("# only\n\n  # comments"), (3, 1, 0, String::new(), Problem::Empty));
+        assert_eq!(position(",1"), (1, 1, 0, ",".to_string(), Problem::MissingValue));
+        assert_eq!(position("1,2,,3"), (1, 5, 2, ",".to_string(), Problem::MissingValue));
+        assert_eq!(position("1,\n  ,2"), (2, 3, 1, ",".to_string(), Problem::MissingValue));
+        assert_eq!(position("1,2 3"), (1, 5, 2, "3".to_string(), Problem::MissingComma));
+        assert_eq!(position("1,2\n3 4"), (2, 3, 3, "4".to_string(), Problem::MissingComma));
+        // columns count characters, not bytes
+        let (line, column, index, token, problem) = position("1,\u{e9},2\n");
+        assert_eq!((line, column, index, token.as_str()), (1, 3, 1, "\u{e9}"));
+        assert!(matches!(problem, Problem::BadValue(_)));
+        let (line, column, index, token, _) = position("1,\n 2, 9223372036854775808");
+        assert_eq!((line, column, index, token.as_str()), (2, 5, 2, "9223372036854775808"));
+    }
+
+    #[test]
+    fn message() {
+        let e = parse_cells::<i64>("1,2 x").unwrap_err();
+        assert_eq!(e.to_string(), "line 1, column 5, cell 2: missing comma before x");
+    }
+}
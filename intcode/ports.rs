@@ -0,0 +1,60 @@
+// where a machine's input comes from and its output goes, so it can be
+// driven without std. console.rs has the stdin and stdout ones
+use alloc::vec::Vec;
+
+use crate::cell::Cell;
+use crate::vm::*;
+
+pub trait Input<C> {
+    // None if there is nothing to read, the machine then waits for input
+    fn read(&mut self) -> Option<C>;
+}
+
+pub trait Output<C> {
+    fn write(&mut self, value: C);
+}
+
+impl<C, I: Iterator<Item = C>> Input<C> for I {
+    fn read(&mut self) -> Option<C> {
+        self.next()
+    }
+}
+
+impl<C> Output<C> for Vec<C> {
+    fn write(&mut self, value: C) {
+        self.push(value)
+    }
+}
+
+// runs until the program halts or the input runs dry. outputs are written
+// before more input is read
+pub fn run<C: Cell>(
+    program: &mut IntCodeProgram<C>,
+    input: &mut impl Input<C>,
+    output: &mut impl Output<C>,
+) -> Result<State, Fault> {
+    run_with(program, input, output, IntCodeProgram::execute)
+}
+
+// like run, with execute replaced by something else that runs the machine
+// until it halts or needs input, e.g. a session recorder
+pub fn run_with<C: Cell>(
+    program: &mut IntCodeProgram<C>,
+    input: &mut impl Input<C>,
+    output: &mut impl Output<C>,
+    mut execute: impl FnMut(&mut IntCodeProgram<C>) -> Result<State, Fault>,
+) -> Result<State, Fault> {
+    loop {
+        let state = execute(program);
+        for value in program.output.drain(..) {
+            output.write(value);
+        }
+        match state? {
+            State::WaitingForInput => match input.read() {
+                Some(value) => program.add_input(value),
+                None => return Ok(State::WaitingForInput),
+            },
+            state => return Ok(state),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::cell::Cell;
+use crate::vm::*;
+
+// what an extension does with each of its parameters
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Role {
+    Read,
+    Write,
+}
+
+// gets the machine and the values of the read parameters in order, and
+// returns one value per write parameter, or None to wait for input without
+// advancing. if the handler changes the ip that's a jump, otherwise the
+// machine moves on to the next instruction
+pub type Handler<C> = Arc<dyn Fn(&mut IntCodeProgram<C>, &[C]) -> Result<Option<Vec<C>>, Fault> + Send + Sync>;
+
+#[derive(Clone)]
+pub struct Extension<C> {
+    pub name: String,
+    pub params: Vec<Role>,
+    pub handler: Handler<C>,
+}
+
+// extra opcodes on top of the built-in ones
+#[derive(Clone)]
+pub struct Registry<C> {
+    extensions: BTreeMap<isize, Extension<C>>,
+}
+
+const BUILTIN: &[isize] = &[
+    OPCODE_ADD,
+    OPCODE_MULT,
+    OPCODE_INPUT,
+    OPCODE_OUTPUT,
+    OPCODE_JUMP_IF_TRUE,
+    OPCODE_JUMP_IF_FALSE,
+    OPCODE_LESS_THAN,
+    OPCODE_EQUALS,
+    OPCODE_HALT,
+];
+
+impl<C> Registry<C> {
+    pub fn new() -> Self {
+        Registry { extensions: BTreeMap::new() }
+    }
+
+    // opcodes are two digits, the rest of the cell holds parameter modes
+    pub fn register<F>(&mut self, opcode: isize, name: &str, params: &[Role], handler: F) -> Result<(), String>
+    where
+        F: Fn(&mut IntCodeProgram<C>, &[C]) -> Result<Option<Vec<C>>, Fault> + Send + Sync + 'static,
+    {
+        if !(1..100).contains(&opcode) {
+            return Err(format!("opcode {} must be between 1 and 99", opcode));
+        }
+        if BUILTIN.contains(&opcode) {
+            return Err(format!("opcode {} is built in", opcode));
+        }
+        if let Some(existing) = self.extensions.get(&opcode) {
+            return Err(format!("opcode {} is already registered as {}", opcode, existing.name));
+        }
+        // an isize has no digits left for more parameter modes
+        if params.len() > 16 {
+            return Err(format!("{} has too many parameters", name));
+        }
+        let extension = Extension {
+            name: name.to_string(),
+            params: params.to_vec(),
+            handler: Arc::new(handler),
+        };
+        self.extensions.insert(opcode, extension);
+        Ok(())
+    }
+
+    pub fn get(&self, opcode: isize) -> Option<&Extension<C>> {
+        self.extensions.get(&opcode)
+    }
+}
+
+impl<C> Default for Registry<C> {
+    fn default() -> Self {
+        Registry::new()
+    }
+}
+
+// a few extensions to try out with `intcode run --extended`
+pub fn samples<C: Cell + 'static>() -> Registry<C> {
+    use Role::*;
+    let mut registry = Registry::<C>::new();
+    let results = |v: C| Ok(Some(vec![v]));
+    registry
+        .register(20, "MIN", &[Read, Read, Write], move |_, args| {
+            results(if args[1] < args[0] { args[1].clone() } else { args[0].clone() })
+        })
+        .unwrap();
+    registry
+        .register(21, "MAX", &[Read, Read, Write], move |_, args| {
+            results(if args[1] > args[0] { args[1].clone() } else { args[0].clone() })
+        })
+        .unwrap();
+    // how many input values are queued, to read without blocking
+    registry
+        .register(22, "PENDING", &[Write], move |machine, _| results(C::from_isize(machine.input.len() as isize)))
+        .unwrap();
+    // jumps to the address when there is no input, day7 style polling
+    registry
+        .register(23, "JUMP_IF_NO_INPUT", &[Read], |machine, args| {
+            if machine.input.is_empty() {
+                let target = args[0].to_isize().filter(|&t| t >= 0).ok_or(Fault::OutOfBounds {
+                    ip: machine.ip,
+                    addr: args[0].to_isize().unwrap_or(isize::MIN),
+                })?;
+                machine.ip = target as usize;
+            }
+            Ok(Some(Vec::new()))
+        })
+        .unwrap();
+    registry
+}
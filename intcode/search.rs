@@ -0,0 +1,262 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::thread;
+
+use crate::symbolic::*;
+use crate::host::*;
+use crate::vm::*;
+
+// candidate solutions analytic() collects before confirming them
+const MAX_CANDIDATES: usize = 1_000_000;
+
+pub struct Search {
+    pub code: Vec<isize>,
+    pub patches: Vec<(usize, isize, isize)>, // address and value range
+    pub output: usize,
+    pub target: isize,
+    pub inputs: Vec<isize>,
+    pub max_steps: usize,
+}
+
+impl Search {
+    // runs the program once with the given patch values applied
+    fn check(&self, program: &mut IntCodeProgram, values: &[isize]) -> bool {
+        // don't use clone(), allocate once and copy
+        program.code.copy_from_slice(&self.code);
+        for (&(addr, _, _), &value) in self.patches.iter().zip(values) {
+            program.code[addr] = value;
+        }
+        program.ip = 0;
+        program.halted = false;
+        program.input.clear();
+        program.input.extend_from_slice(&self.inputs);
+        program.output.clear();
+
+        // invalid patch values often make the program crash
+        for _ in 0..self.max_steps {
+            match program.step() {
+                Ok(State::Running) => {}
+                Ok(State::Halted) => return program.code[self.output] == self.target,
+                Ok(State::WaitingForInput) | Err(_) => return false,
+            }
+        }
+        false
+    }
+
+    // all combinations, with the first patch restricted to `first`
+    fn scan(&self, first: (isize, isize)) -> Vec<Vec<isize>> {
+        let mut res = Vec::new();
+        let mut program = IntCodeProgram::new(self.code.clone());
+        let mut ranges = self.patches.iter().map(|&(_, lo, hi)| (lo, hi)).collect::<Vec<(isize, isize)>>();
+        ranges[0] = first;
+        if ranges.iter().any(|(lo, hi)| lo >= hi) {
+            return res;
+        }
+
+        let mut values = ranges.iter().map(|r| r.0).collect::<Vec<isize>>();
+        let all = (0..values.len()).collect::<Vec<usize>>();
+        loop {
+            if self.check(&mut program, &values) {
+                res.push(values.clone());
+            }
+            if !advance(&mut values, &all, &ranges) {
+                return res;
+            }
+        }
+    }
+
+    pub fn exhaustive(&self) -> Vec<Vec<isize>> {
+        let (_, lo, hi) = self.patches[0];
+        self.scan((lo, hi))
+    }
+
+    // splits the range of the first patch between threads
+    pub fn parallel(&self) -> Vec<Vec<isize>> {
+        let (_, lo, hi) = self.patches[0];
+        let threads = thread::available_parallelism().map_or(4, |n| n.get());
+        thread::scope(|s| {
+            let handles = chunks(lo, hi, threads).into_iter().map(|range| s.spawn(move || self.scan(range))).collect::<Vec<_>>();
+            handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+        })
+    }
+
+    // solves the output value directly if it is a linear function of the
+    // patched cells; None if it isn't, or if the cells it doesn't depend on
+    // make more than MAX_CANDIDATES combinations
+    pub fn analytic(&self) -> Option<Vec<Vec<isize>>> {
+        let mut explorer = Explorer::new();
+        explorer.inputs = self.inputs.clone();
+        explorer.max_steps = self.max_steps;
+        let cells = self.patches.iter().map(|&(addr, _, _)| (addr, format!("[{}]", addr))).collect::<Vec<(usize, String)>>();
+        let paths = explorer.explore(&self.code, &cells);
+        if explorer.truncated {
+            return None;
+        }
+
+        let ranges = self.patches.iter().map(|&(_, lo, hi)| (lo, hi)).collect::<Vec<(isize, isize)>>();
+        let range_map = ranges.iter().cloned().enumerate().collect::<BTreeMap<usize, (isize, isize)>>();
+        let mut res = Vec::new();
+        for path in paths {
+            if path.status != Status::Halted {
+                return None;
+            }
+            let (coeffs, c) = path.memory.get(self.output)?.linear()?;
+            for solution in solve_linear(&coeffs, c, self.target, &range_map).ok()? {
+                // cells that don't affect the output can take any value
+                let free = (0..ranges.len()).filter(|i| !solution.contains_key(i)).collect::<Vec<usize>>();
+                let combinations = free.iter().try_fold(1u128, |n, &i| n.checked_mul((ranges[i].1 as i128 - ranges[i].0 as i128).max(0) as u128))?;
+                if combinations > (MAX_CANDIDATES - res.len()) as u128 {
+                    return None;
+                }
+                let mut values = (0..ranges.len()).map(|i| solution.get(&i).cloned().unwrap_or(ranges[i].0)).collect::<Vec<isize>>();
+                loop {
+                    res.push(values.clone());
+                    if !advance(&mut values, &free, &ranges) {
+                        break;
+                    }
+                }
+            }
+        }
+
+        // the symbolic run doesn't know about everything that can crash the
+        // machine, so confirm each candidate
+        let mut program = IntCodeProgram::new(self.code.clone());
+        res.retain(|values| self.check(&mut program, values));
+        res.sort();
+        res.dedup();
+        Some(res)
+    }
+}
+
+// lo..hi in about `n` parts. the range can be wider than isize::MAX
+fn chunks(lo: isize, hi: isize, n: usize) -> Vec<(isize, isize)> {
+    let chunk = ((hi as i128 - lo as i128).max(0) as u128 / n as u128).max(1) as usize;
+    (lo..hi).step_by(chunk).map(|start| (start, (start as i128 + chunk as i128).min(hi as i128) as isize)).collect()
+}
+
+// advances the values at `indices` like an odometer, last index first;
+// false once every combination has been visited
+fn advance(values: &mut [isize], indices: &[usize], ranges: &[(isize, isize)]) -> bool {
+    for &i in indices.iter().rev() {
+        values[i] += 1;
+        if values[i] < ranges[i].1 {
+            return true;
+        }
+        values[i] = ranges[i].0;
+    }
+    false
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut patches = Vec::new();
+    let mut output = 0;
+    let mut target = None;
+    let mut inputs = Vec::new();
+    let mut strategy = "auto".to_string();
+    let mut max_steps = 100_000;
+    let mut filename = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let number = |s: String| s.parse::<isize>().map_err(|_| invalid(format!("bad number {}", s)));
+        let count = |s: String| s.parse::<usize>().map_err(|_| invalid(format!("{} needs a non-negative number, not {}", arg, s)));
+        match arg.as_str() {
+            // --patch ADDR=LO..HI
+            "--patch" => {
+                let value = value(&mut iter, arg)?;
+                let mut parts = value.splitn(2, '=');
+                let addr = parts.next().and_then(|a| a.parse::<usize>().ok());
+                match (addr, parts.next().and_then(parse_range)) {
+                    (Some(addr), Some((lo, hi))) => patches.push((addr, lo, hi)),
+                    _ => return Err(invalid(format!("bad patch {}", value))),
+                }
+            }
+            "--output" => output = count(value(&mut iter, arg)?)?,
+            "--target" => target = Some(number(value(&mut iter, arg)?)?),
+            "--input" => inputs.push(number(value(&mut iter, arg)?)?),
+            "--strategy" => strategy = value(&mut iter, arg)?,
+            "--max-steps" => max_steps = count(value(&mut iter, arg)?)?,
+            _ => filename = Some(arg.as_str()),
+        }
+    }
+
+    let target = target.ok_or_else(|| invalid("missing --target".to_string()))?;
+    if patches.is_empty() {
+        return Err(invalid("missing --patch".to_string()));
+    }
+    let program = load_program(filename)?;
+    if let Some(&(addr, _, _)) = patches.iter().find(|p| p.0 >= program.code.len()) {
+        return Err(invalid(format!("patch address {} is outside of the program", addr)));
+    }
+    if output >= program.code.len() {
+        return Err(invalid(format!("output address {} is outside of the program", output)));
+    }
+
+    let search = Search {
+        code: program.code,
+        patches,
+        output,
+        target,
+        inputs,
+        max_steps,
+    };
+
+    let solutions = match strategy.as_str() {
+        "exhaustive" => Ok(search.exhaustive()),
+        "parallel" => Ok(search.parallel()),
+        "analytic" => search.analytic().ok_or_else(|| invalid("output is not a linear function of the patched cells, or has too many solutions".to_string())),
+        "auto" => Ok(search.analytic().unwrap_or_else(|| search.parallel())),
+        _ => Err(invalid(format!("unknown strategy {}", strategy))),
+    };
+
+    let solutions = solutions?;
+    for values in &solutions {
+        let values = search
+            .patches
+            .iter()
+            .zip(values)
+            .map(|(p, v)| format!("[{}] = {}", p.0, v))
+            .collect::<Vec<String>>();
+        println!("{}", values.join(", "));
+    }
+    println!("{} solutions", solutions.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wide_ranges() {
+        assert_eq!(chunks(0, 10, 3), vec![(0, 3), (3, 6), (6, 9), (9, 10)]);
+        assert_eq!(chunks(0, 2, 4), vec![(0, 1), (1, 2)]);
+        assert_eq!(chunks(5, 5, 4), vec![]);
+        let all = chunks(isize::MIN, isize::MAX, 1);
+        assert_eq!(all, vec![(isize::MIN, isize::MAX)]);
+        let halves = chunks(isize::MIN, isize::MAX, 2);
+        assert_eq!(halves.first().map(|c| c.0), Some(isize::MIN));
+        assert_eq!(halves.last().map(|c| c.1), Some(isize::MAX));
+        assert!(halves.windows(2).all(|w| w[0].1 == w[1].0));
+    }
+
+    // [9] = [1] + [2], where [5] doesn't matter
+    fn adder(free: (isize, isize)) -> Search {
+        Search {
+            code: vec![1101, 0, 0, 9, 99, 0, 0, 0, 0, 0],
+            patches: vec![(1, 0, 10), (2, 0, 10), (5, free.0, free.1)],
+            output: 9,
+            target: 4,
+            inputs: Vec::new(),
+            max_steps: 100,
+        }
+    }
+
+    #[test]
+    fn free_cells() {
+        let solutions = adder((0, 2)).analytic().unwrap();
+        assert_eq!(solutions.len(), 10);
+        assert_eq!(solutions, adder((0, 2)).exhaustive());
+        assert_eq!(adder((isize::MIN, isize::MAX)).analytic(), None);
+    }
+}
@@ -0,0 +1,153 @@
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use crate::ascii::*;
+use crate::host::*;
+use crate::vm::*;
+
+// runs one machine for one client until it halts, faults or the client
+// goes away
+fn serve_client(mut program: IntCodeProgram, stream: TcpStream, ascii: bool) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let mut text = AsciiOutput::new();
+    loop {
+        let state = program.execute();
+        let mut out = String::new();
+        while let Some(output) = program.take_output() {
+            if ascii {
+                out.push_str(&text.render(&output));
+            } else {
+                out.push_str(&format!("{}\n", output));
+            }
+        }
+        writer.write_all(out.as_bytes())?;
+
+        match state {
+            Ok(State::WaitingForInput) => {
+                let mut line = String::new();
+                if reader.read_line(&mut line)? == 0 {
+                    return Ok(());
+                }
+                if ascii {
+                    match encode_line::<isize>(&line) {
+                        Ok(codes) => program.input.extend(codes),
+                        Err(e) => writeln!(writer, "error: {}", e)?,
+                    }
+                } else {
+                    match line.trim().parse() {
+                        Ok(value) => program.add_input(value),
+                        Err(_) => writeln!(writer, "error: {} is not a number", line.trim())?,
+                    }
+                }
+            }
+            Ok(_) => return Ok(()),
+            Err(fault) => {
+                writeln!(writer, "error: {}", fault)?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut port = None;
+    let mut ascii = false;
+    let mut checked = false;
+    let mut filename = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--port" => {
+                let value = value(&mut iter, arg)?;
+                port = Some(value.parse::<u16>().map_err(|_| invalid(format!("bad port {}", value)))?);
+            }
+            "--ascii" => ascii = true,
+            "--checked" => checked = true,
+            _ => filename = Some(arg.as_str()),
+        }
+    }
+
+    let port = port.ok_or_else(|| invalid("missing --port".to_string()))?;
+    // read the program before listening, stdin isn't available per client
+    let mut program = load_program(filename)?;
+    program.checked = checked;
+
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!("listening on {}", listener.local_addr()?);
+    serve(listener, program, ascii)
+}
+
+// every client gets its own copy of the program
+fn serve(listener: TcpListener, program: IntCodeProgram, ascii: bool) -> io::Result<()> {
+    for stream in listener.incoming() {
+        // one failed connection mustn't take the others down
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("accept: {}", e);
+                continue;
+            }
+        };
+        // the client may already be gone again
+        let peer = stream.peer_addr().map_or_else(|_| "unknown".to_string(), |a| a.to_string());
+        let program = program.clone();
+        eprintln!("{}: connected", peer);
+        thread::spawn(move || match serve_client(program, stream, ascii) {
+            Ok(()) => eprintln!("{}: done", peer),
+            Err(e) => eprintln!("{}: {}", peer, e),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // adds up its inputs, printing the running total after each
+    const TOTAL: &str = "3,20,1,20,21,21,4,21,1105,1,0,0,0,0,0,0,0,0,0,0,0,0";
+
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Client {
+        fn connect(addr: std::net::SocketAddr) -> Client {
+            let writer = TcpStream::connect(addr).unwrap();
+            Client {
+                reader: BufReader::new(writer.try_clone().unwrap()),
+                writer,
+            }
+        }
+
+        fn send(&mut self, value: isize) -> String {
+            writeln!(self.writer, "{}", value).unwrap();
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            line.trim_end().to_string()
+        }
+    }
+
+    #[test]
+    fn clients_are_isolated() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let program = TOTAL.parse::<IntCodeProgram>().unwrap();
+        thread::spawn(move || serve(listener, program, false));
+
+        let mut a = Client::connect(addr);
+        let mut b = Client::connect(addr);
+        assert_eq!(a.send(1), "1");
+        assert_eq!(b.send(10), "10");
+        assert_eq!(a.send(2), "3");
+        assert_eq!(b.send(20), "30");
+        assert_eq!(a.send(3), "6");
+        assert_eq!(b.send(-30), "0");
+    }
+}
@@ -0,0 +1,279 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+
+use crate::bigint::BigInt;
+use crate::cell::Cell;
+use crate::registry;
+use crate::host::*;
+use crate::vm::*;
+
+// what happened at which step, with values as text so any cell type works
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Event {
+    Input(u64, String),
+    Output(u64, String),
+    End(u64, String),
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Input(step, value) => write!(f, "{} input {}", step, value),
+            Event::Output(step, value) => write!(f, "{} output {}", step, value),
+            Event::End(step, how) => write!(f, "{} {}", step, how),
+        }
+    }
+}
+
+impl Event {
+    fn parse(line: &str) -> Option<Event> {
+        let mut parts = line.splitn(3, ' ');
+        let step = parts.next()?.parse::<u64>().ok()?;
+        let kind = parts.next()?;
+        let rest = parts.next();
+        match (kind, rest) {
+            ("input", Some(value)) => Some(Event::Input(step, value.to_string())),
+            ("output", Some(value)) => Some(Event::Output(step, value.to_string())),
+            (how, rest) => Some(Event::End(step, rest.map_or(how.to_string(), |r| format!("{} {}", how, r)))),
+        }
+    }
+}
+
+// how `intcode run` set up the machine, saved with a session so replay
+// sets it up the same way
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Options {
+    pub cells: String,
+    pub checked: bool,
+    // with the sample extension opcodes
+    pub extended: bool,
+    pub strict: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            cells: "isize".to_string(),
+            checked: false,
+            extended: false,
+            strict: false,
+        }
+    }
+}
+
+impl Options {
+    pub fn apply<C: Cell + 'static>(&self, program: &mut IntCodeProgram<C>) {
+        program.checked = self.checked;
+        program.strict = self.strict;
+        if self.extended {
+            program.extensions = registry::samples();
+        }
+    }
+}
+
+// FNV-1a over the program text, the same for every cell type
+pub fn fingerprint<C: Cell>(code: &[C]) -> u64 {
+    let text = code.iter().map(|c| c.to_string()).collect::<Vec<String>>().join(",");
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+// logs the I/O of a machine, counting executed instructions
+pub struct Recorder {
+    pub options: Options,
+    // the fingerprint of the program, if it could be loaded
+    pub program: Option<u64>,
+    pub steps: u64,
+    pub events: Vec<Event>,
+}
+
+impl Recorder {
+    pub fn new(options: &Options) -> Self {
+        Recorder {
+            options: options.clone(),
+            program: None,
+            steps: 0,
+            events: Vec::new(),
+        }
+    }
+
+    // like execute(), logging every input consumed and output produced
+    pub fn execute<C: Cell>(&mut self, program: &mut IntCodeProgram<C>) -> Result<State, Fault> {
+        loop {
+            let next_input = program.input.first().cloned();
+            let inputs = program.input.len();
+            let outputs = program.output.len();
+            let state = program.step();
+            if state == Ok(State::WaitingForInput) {
+                return state;
+            }
+            self.steps += 1;
+            if let Some(value) = next_input.filter(|_| program.input.len() < inputs) {
+                self.events.push(Event::Input(self.steps, value.to_string()));
+            }
+            if let Some(value) = program.output.get(outputs) {
+                self.events.push(Event::Output(self.steps, value.to_string()));
+            }
+            match state {
+                Ok(State::Running) => {}
+                Ok(State::Halted) => {
+                    self.end("halt");
+                    return state;
+                }
+                Err(fault) => {
+                    self.end(&format!("fault {}", fault));
+                    return state;
+                }
+                Ok(State::WaitingForInput) => unreachable!(),
+            }
+        }
+    }
+
+    pub fn end(&mut self, how: &str) {
+        self.events.push(Event::End(self.steps, how.to_string()));
+    }
+
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        let mut text = format!("# intcode session\n# cells {}\n", self.options.cells);
+        if let Some(program) = self.program {
+            text.push_str(&format!("# program {:016x}\n", program));
+        }
+        if self.options.checked {
+            text.push_str("# checked\n");
+        }
+        if self.options.extended {
+            text.push_str("# extended\n");
+        }
+        if self.options.strict {
+            text.push_str("# strict\n");
+        }
+        for event in &self.events {
+            text.push_str(&format!("{}\n", event));
+        }
+        fs::write(filename, text)
+    }
+
+    pub fn load(filename: &str) -> io::Result<Recorder> {
+        let mut recorder = Recorder::new(&Options::default());
+        for (n, line) in fs::read_to_string(filename)?.lines().enumerate() {
+            let bad = |what: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}: bad {} {}", filename, n + 1, what, line));
+            if let Some(comment) = line.strip_prefix('#') {
+                match comment.trim().split_once(' ') {
+                    Some(("cells", cells)) => recorder.options.cells = cells.to_string(),
+                    Some(("program", hash)) => recorder.program = Some(u64::from_str_radix(hash, 16).map_err(|_| bad("program"))?),
+                    _ if comment.trim() == "checked" => recorder.options.checked = true,
+                    _ if comment.trim() == "extended" => recorder.options.extended = true,
+                    _ if comment.trim() == "strict" => recorder.options.strict = true,
+                    _ => {}
+                }
+            } else if !line.trim().is_empty() {
+                let event = Event::parse(line.trim()).ok_or_else(|| bad("event"))?;
+                recorder.events.push(event);
+            }
+        }
+        Ok(recorder)
+    }
+}
+
+// runs the program with the recorded inputs and compares what happens
+fn replay_cells<C: Cell + 'static>(session: &Recorder, filename: Option<&str>) -> io::Result<Vec<Event>>
+where
+    C::Err: Error + Send + Sync + 'static,
+{
+    let mut program = load_cells::<C>(filename)?;
+    // sessions from before the fingerprint was saved replay against anything
+    if session.program.is_some_and(|p| p != fingerprint(&program.code)) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "the session was recorded with a different program"));
+    }
+    session.options.apply(&mut program);
+    for event in &session.events {
+        if let Event::Input(_, value) = event {
+            let value = value.parse::<C>().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            program.add_input(value);
+        }
+    }
+    let mut recorder = Recorder::new(&session.options);
+    if recorder.execute(&mut program) == Ok(State::WaitingForInput) {
+        recorder.end("needs input");
+    }
+    Ok(recorder.events)
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let (session, filename) = match args {
+        [session] => (session, None),
+        [session, filename] => (session, Some(filename.as_str())),
+        _ => return Err(invalid("usage: intcode replay <session> [program]".to_string())),
+    };
+    let session = Recorder::load(session)?;
+    let events = match session.options.cells.as_str() {
+        "isize" => replay_cells::<isize>(&session, filename)?,
+        "i64" => replay_cells::<i64>(&session, filename)?,
+        "i128" => replay_cells::<i128>(&session, filename)?,
+        "big" => replay_cells::<BigInt>(&session, filename)?,
+        cells => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown cell type {}", cells))),
+    };
+
+    // the first event that differs tells where behaviour diverges
+    let len = session.events.len().max(events.len());
+    match (0..len).find(|&i| session.events.get(i) != events.get(i)) {
+        None => {
+            println!("{} events match", events.len());
+            Ok(())
+        }
+        Some(i) => {
+            let (want, got) = (session.events.get(i), events.get(i));
+            let step = |e: Option<&Event>| match e {
+                Some(Event::Input(s, _)) | Some(Event::Output(s, _)) | Some(Event::End(s, _)) => *s,
+                None => u64::MAX,
+            };
+            let show = |e: Option<&Event>| e.map_or("nothing".to_string(), |e| e.to_string());
+            println!("diverges at step {}", step(want).min(step(got)));
+            println!("  recorded: {}", show(want));
+            println!("  replayed: {}", show(got));
+            Err(io::Error::new(io::ErrorKind::InvalidData, "replay diverged"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::path::Path;
+
+    use super::*;
+
+    fn corpus(name: &str) -> String {
+        Path::new(file!()).with_file_name("fuzz").join("corpus").join(name).to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn replay_checks_the_program() {
+        let echo = corpus("day5-example3.txt");
+        let options = Options {
+            cells: "i64".to_string(),
+            checked: true,
+            ..Options::default()
+        };
+        let mut program = load_cells::<i64>(Some(&echo)).unwrap();
+        options.apply(&mut program);
+        program.add_input(7);
+        let mut recorder = Recorder::new(&options);
+        recorder.program = Some(fingerprint(&program.code));
+        assert_eq!(recorder.execute(&mut program), Ok(State::Halted));
+
+        let saved = env::temp_dir().join(format!("intcode-session-{}", std::process::id()));
+        let saved = saved.to_string_lossy();
+        recorder.save(&saved).unwrap();
+        let session = Recorder::load(&saved);
+        let _ = fs::remove_file(saved.as_ref());
+        let session = session.unwrap();
+        assert_eq!(session.options, options);
+        assert_eq!(session.program, recorder.program);
+
+        assert_eq!(replay_cells::<i64>(&session, Some(&echo)).unwrap(), recorder.events);
+        let other = replay_cells::<i64>(&session, Some(&corpus("day5-example4.txt"))).unwrap_err();
+        assert_eq!(other.to_string(), "the session was recorded with a different program");
+    }
+}
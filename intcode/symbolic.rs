@@ -0,0 +1,725 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+use std::io;
+use std::mem;
+use std::ptr;
+use std::rc::Rc;
+
+use crate::host::*;
+use crate::vm::*;
+
+// expressions share their subterms, a loop doubling a cell n times makes n
+// nodes that expand to 2^n. everything walking them visits each node once,
+// remembered by its address
+#[derive(Debug)]
+pub enum Sym {
+    Const(isize),
+    Var(usize),
+    Add(Rc<Sym>, Rc<Sym>),
+    Mul(Rc<Sym>, Rc<Sym>),
+    Lt(Rc<Sym>, Rc<Sym>),
+    Eq(Rc<Sym>, Rc<Sym>),
+    // a read through an address that is not known
+    Load(Rc<Sym>),
+}
+
+impl PartialEq for Sym {
+    fn eq(&self, other: &Sym) -> bool {
+        same(self, other, &mut HashSet::new())
+    }
+}
+
+impl Eq for Sym {}
+
+// pairs already known to be equal aren't compared again
+fn same(a: &Sym, b: &Sym, equal: &mut HashSet<(*const Sym, *const Sym)>) -> bool {
+    if ptr::eq(a, b) || equal.contains(&(a as *const Sym, b as *const Sym)) {
+        return true;
+    }
+    let res = match (a, b) {
+        (Sym::Const(x), Sym::Const(y)) => x == y,
+        (Sym::Var(x), Sym::Var(y)) => x == y,
+        _ if mem::discriminant(a) == mem::discriminant(b) => {
+            a.operands().iter().zip(b.operands()).all(|(x, y)| same(x, y, equal))
+        }
+        _ => false,
+    };
+    if res {
+        equal.insert((a as *const Sym, b as *const Sym));
+    }
+    res
+}
+
+fn constant(s: &Sym) -> Option<isize> {
+    match s {
+        Sym::Const(c) => Some(*c),
+        _ => None,
+    }
+}
+
+// constructors fold constants and keep them on the right-hand side
+fn add(a: Rc<Sym>, b: Rc<Sym>) -> Rc<Sym> {
+    match (constant(&a), constant(&b)) {
+        (Some(x), Some(y)) if x.checked_add(y).is_some() => Rc::new(Sym::Const(x + y)),
+        (Some(0), _) => b,
+        (_, Some(0)) => a,
+        (Some(_), None) => add(b, a),
+        (None, Some(y)) => match &*a {
+            Sym::Add(inner, c) => match constant(c).and_then(|x| x.checked_add(y)) {
+                Some(sum) => add(inner.clone(), Rc::new(Sym::Const(sum))),
+                None => Rc::new(Sym::Add(a, b)),
+            },
+            _ => Rc::new(Sym::Add(a, b)),
+        },
+        _ => Rc::new(Sym::Add(a, b)),
+    }
+}
+
+fn mul(a: Rc<Sym>, b: Rc<Sym>) -> Rc<Sym> {
+    match (constant(&a), constant(&b)) {
+        (Some(x), Some(y)) if x.checked_mul(y).is_some() => Rc::new(Sym::Const(x * y)),
+        (Some(0), _) | (_, Some(0)) => Rc::new(Sym::Const(0)),
+        (Some(1), _) => b,
+        (_, Some(1)) => a,
+        (Some(_), None) => mul(b, a),
+        _ => Rc::new(Sym::Mul(a, b)),
+    }
+}
+
+fn compare(a: Rc<Sym>, b: Rc<Sym>, less: bool) -> Rc<Sym> {
+    match (constant(&a), constant(&b)) {
+        (Some(x), Some(y)) => Rc::new(Sym::Const(if less { x < y } else { x == y } as isize)),
+        _ if !less && a == b => Rc::new(Sym::Const(1)),
+        _ if less => Rc::new(Sym::Lt(a, b)),
+        _ => Rc::new(Sym::Eq(a, b)),
+    }
+}
+
+impl Sym {
+    fn precedence(&self) -> u32 {
+        match self {
+            Sym::Add(_, _) => 2,
+            Sym::Mul(_, _) => 3,
+            Sym::Lt(_, _) | Sym::Eq(_, _) => 1,
+            _ => 4,
+        }
+    }
+
+    fn operands(&self) -> Vec<&Rc<Sym>> {
+        match self {
+            Sym::Const(_) | Sym::Var(_) => Vec::new(),
+            Sym::Add(a, b) | Sym::Mul(a, b) | Sym::Lt(a, b) | Sym::Eq(a, b) => vec![a, b],
+            Sym::Load(a) => vec![a],
+        }
+    }
+
+    // subterms used more than once are written as temporaries t1, t2... and
+    // defined after the expression: `t2 + t2 where t1 = x + x, t2 = t1 + t1`
+    pub fn render(&self, names: &[String]) -> String {
+        let mut uses = HashMap::new();
+        self.count_uses(&mut uses);
+        let mut renderer = Renderer {
+            names,
+            uses,
+            temps: HashMap::new(),
+            defs: Vec::new(),
+        };
+        let mut out = String::new();
+        renderer.render_into(self, 0, &mut out);
+        if !renderer.defs.is_empty() {
+            write!(out, " where {}", renderer.defs.join(", ")).unwrap();
+        }
+        out
+    }
+
+    fn count_uses(&self, uses: &mut HashMap<*const Sym, usize>) {
+        for e in self.operands() {
+            let count = uses.entry(Rc::as_ptr(e)).or_insert(0);
+            *count += 1;
+            if *count == 1 {
+                e.count_uses(uses);
+            }
+        }
+    }
+
+    pub fn symbols(&self, res: &mut BTreeSet<usize>) {
+        self.collect_symbols(res, &mut HashSet::new());
+    }
+
+    fn collect_symbols(&self, res: &mut BTreeSet<usize>, seen: &mut HashSet<*const Sym>) {
+        if !seen.insert(self as *const Sym) {
+            return;
+        }
+        if let Sym::Var(v) = self {
+            res.insert(*v);
+        }
+        for e in self.operands() {
+            e.collect_symbols(res, seen);
+        }
+    }
+
+    // None if the value depends on unknown memory or overflows
+    pub fn eval(&self, values: &BTreeMap<usize, isize>) -> Option<isize> {
+        self.eval_shared(values, &mut HashMap::new())
+    }
+
+    fn eval_shared(&self, values: &BTreeMap<usize, isize>, known: &mut HashMap<*const Sym, Option<isize>>) -> Option<isize> {
+        if let Some(&value) = known.get(&(self as *const Sym)) {
+            return value;
+        }
+        let mut eval = |e: &Rc<Sym>| e.eval_shared(values, known);
+        let value = match self {
+            Sym::Const(c) => Some(*c),
+            Sym::Var(v) => values.get(v).cloned(),
+            Sym::Add(a, b) => eval(a).and_then(|a| a.checked_add(eval(b)?)),
+            Sym::Mul(a, b) => eval(a).and_then(|a| a.checked_mul(eval(b)?)),
+            Sym::Lt(a, b) => eval(a).and_then(|a| Some((a < eval(b)?) as isize)),
+            Sym::Eq(a, b) => eval(a).and_then(|a| Some((a == eval(b)?) as isize)),
+            Sym::Load(_) => None,
+        };
+        known.insert(self as *const Sym, value);
+        value
+    }
+
+    // sum of coefficient * symbol plus a constant, if the value is linear
+    pub fn linear(&self) -> Option<(BTreeMap<usize, isize>, isize)> {
+        self.linear_shared(&mut HashMap::new())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn linear_shared(&self, known: &mut HashMap<*const Sym, Option<(BTreeMap<usize, isize>, isize)>>) -> Option<(BTreeMap<usize, isize>, isize)> {
+        if let Some(linear) = known.get(&(self as *const Sym)) {
+            return linear.clone();
+        }
+        let linear = match self {
+            Sym::Const(c) => Some((BTreeMap::new(), *c)),
+            Sym::Var(v) => Some((vec![(*v, 1)].into_iter().collect(), 0)),
+            Sym::Add(a, b) => a.linear_shared(known).zip(b.linear_shared(known)).and_then(|((mut coeffs, c1), (other, c2))| {
+                for (v, k) in other {
+                    let sum = coeffs.get(&v).unwrap_or(&0).checked_add(k)?;
+                    coeffs.insert(v, sum);
+                }
+                coeffs.retain(|_, k| *k != 0);
+                Some((coeffs, c1.checked_add(c2)?))
+            }),
+            Sym::Mul(a, b) => a.linear_shared(known).zip(b.linear_shared(known)).and_then(|((ca, ka), (cb, kb))| {
+                let (coeffs, c, factor) = match (ca.is_empty(), cb.is_empty()) {
+                    (true, _) => (cb, kb, ka),
+                    (_, true) => (ca, ka, kb),
+                    _ => return None,
+                };
+                let mut scaled = BTreeMap::new();
+                for (v, k) in coeffs {
+                    scaled.insert(v, k.checked_mul(factor)?);
+                }
+                scaled.retain(|_, k| *k != 0);
+                Some((scaled, c.checked_mul(factor)?))
+            }),
+            _ => None,
+        };
+        known.insert(self as *const Sym, linear.clone());
+        linear
+    }
+}
+
+struct Renderer<'a> {
+    names: &'a [String],
+    // how often each node is an operand in the expression
+    uses: HashMap<*const Sym, usize>,
+    temps: HashMap<*const Sym, usize>,
+    defs: Vec<String>,
+}
+
+impl Renderer<'_> {
+    fn is_temp(&self, e: &Sym) -> bool {
+        !e.operands().is_empty() && self.uses.get(&(e as *const Sym)).is_some_and(|&n| n > 1)
+    }
+
+    fn precedence(&self, e: &Sym) -> u32 {
+        if self.is_temp(e) {
+            4
+        } else {
+            e.precedence()
+        }
+    }
+
+    // an operand, through its temporary if it is shared
+    fn operand(&mut self, e: &Sym, depth: usize, out: &mut String) {
+        if !self.is_temp(e) {
+            return self.render_into(e, depth, out);
+        }
+        let key = e as *const Sym;
+        if !self.temps.contains_key(&key) {
+            let mut def = String::new();
+            self.render_into(e, 0, &mut def);
+            self.temps.insert(key, self.temps.len() + 1);
+            self.defs.push(format!("t{} = {}", self.temps.len(), def));
+        }
+        write!(out, "t{}", self.temps[&key]).unwrap();
+    }
+
+    // unshared expressions can still be long chains, so deep ones are cut off
+    fn render_into(&mut self, e: &Sym, depth: usize, out: &mut String) {
+        if depth > 32 {
+            out.push_str("...");
+            return;
+        }
+        let prec = e.precedence();
+        let mut binary = |r: &mut Self, a: &Sym, op: &str, b: &Sym| {
+            for (i, x) in [a, b].iter().enumerate() {
+                if i == 1 {
+                    write!(out, " {} ", op).unwrap();
+                }
+                let wrap = r.precedence(x) < prec || (i == 1 && r.precedence(x) == prec);
+                if wrap {
+                    out.push('(');
+                }
+                r.operand(x, depth + 1, out);
+                if wrap {
+                    out.push(')');
+                }
+            }
+        };
+        match e {
+            Sym::Const(c) => write!(out, "{}", c).unwrap(),
+            Sym::Var(v) => out.push_str(&self.names[*v]),
+            Sym::Add(a, b) => binary(self, a, "+", b),
+            Sym::Mul(a, b) => binary(self, a, "*", b),
+            Sym::Lt(a, b) => binary(self, a, "<", b),
+            Sym::Eq(a, b) => binary(self, a, "==", b),
+            Sym::Load(a) => {
+                out.push_str("mem[");
+                self.operand(a, depth + 1, out);
+                out.push(']');
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+    Halted,
+    StepLimit,
+    Error(String),
+}
+
+#[derive(Clone)]
+pub struct Path {
+    pub memory: Vec<Rc<Sym>>,
+    pub ip: usize,
+    pub steps: usize,
+    pub conditions: Vec<(Rc<Sym>, bool)>,
+    pub outputs: Vec<Rc<Sym>>,
+    pub inputs: usize,
+    pub status: Status,
+}
+
+impl Path {
+    fn read_param(&mut self, param_num: usize) -> Result<Rc<Sym>, String> {
+        let opcode = constant(&self.memory[self.ip]).unwrap_or_default();
+        let mode = (opcode / 10isize.pow(param_num as u32 + 1)) % 10;
+        let param = self.cell(self.ip + param_num)?;
+        match (mode, constant(&param)) {
+            (PMODE_IMMEDIATE, _) => Ok(param),
+            (PMODE_POSITION, Some(addr)) => self.cell(addr as usize),
+            (PMODE_POSITION, None) => {
+                // the machine would crash on reads outside of its memory
+                let len = Rc::new(Sym::Const(self.memory.len() as isize));
+                self.conditions.push((compare(param.clone(), Rc::new(Sym::Const(0)), true), false));
+                self.conditions.push((compare(param.clone(), len, true), true));
+                Ok(Rc::new(Sym::Load(param)))
+            }
+            _ => Err(format!("{}: invalid parameter mode {}", self.ip, mode)),
+        }
+    }
+
+    fn write_param(&mut self, param_num: usize, value: Rc<Sym>) -> Result<(), String> {
+        let addr = self.cell(self.ip + param_num)?;
+        match constant(&addr) {
+            Some(addr) if addr >= 0 && (addr as usize) < self.memory.len() => {
+                self.memory[addr as usize] = value;
+                Ok(())
+            }
+            Some(addr) => Err(format!("{}: write to address {} out of bounds", self.ip, addr)),
+            None => Err(format!("{}: write to a symbolic address", self.ip)),
+        }
+    }
+
+    fn cell(&self, addr: usize) -> Result<Rc<Sym>, String> {
+        self.memory.get(addr).cloned().ok_or_else(|| format!("{}: read from address {} out of bounds", self.ip, addr))
+    }
+
+    // a condition that is already known on this path
+    fn known(&self, cond: &Rc<Sym>) -> Option<bool> {
+        self.conditions.iter().find(|(c, _)| c == cond).map(|&(_, taken)| taken)
+    }
+}
+
+pub struct Explorer {
+    pub names: Vec<String>,
+    pub inputs: Vec<isize>,
+    pub max_steps: usize,
+    pub max_paths: usize,
+    // some paths were not explored because of max_paths
+    pub truncated: bool,
+    paths: usize,
+}
+
+impl Explorer {
+    pub fn new() -> Self {
+        Explorer {
+            names: Vec::new(),
+            inputs: Vec::new(),
+            max_steps: 100_000,
+            max_paths: 64,
+            truncated: false,
+            paths: 0,
+        }
+    }
+
+    pub fn symbol(&mut self, name: &str) -> Rc<Sym> {
+        let index = match self.names.iter().position(|n| n == name) {
+            Some(i) => i,
+            None => {
+                self.names.push(name.to_string());
+                self.names.len() - 1
+            }
+        };
+        Rc::new(Sym::Var(index))
+    }
+
+    // runs all feasible paths; `symbols` replace the given memory cells
+    pub fn initial_memory(&mut self, code: &[isize], symbols: &[(usize, String)]) -> Vec<Rc<Sym>> {
+        let mut memory = code.iter().map(|&c| Rc::new(Sym::Const(c))).collect::<Vec<Rc<Sym>>>();
+        for (addr, name) in symbols {
+            memory[*addr] = self.symbol(name);
+        }
+        memory
+    }
+
+    pub fn explore(&mut self, code: &[isize], symbols: &[(usize, String)]) -> Vec<Path> {
+        self.paths = 1;
+        self.truncated = false;
+        let mut pending = vec![Path {
+            memory: self.initial_memory(code, symbols),
+            ip: 0,
+            steps: 0,
+            conditions: Vec::new(),
+            outputs: Vec::new(),
+            inputs: 0,
+            status: Status::Halted,
+        }];
+        let mut done = Vec::new();
+        while let Some(mut path) = pending.pop() {
+            if let Err(e) = self.run_path(&mut path, &mut pending) {
+                path.status = Status::Error(e);
+            }
+            done.push(path);
+        }
+        done
+    }
+
+    fn run_path(&mut self, path: &mut Path, pending: &mut Vec<Path>) -> Result<(), String> {
+        loop {
+            if path.steps >= self.max_steps {
+                path.status = Status::StepLimit;
+                return Ok(());
+            }
+            path.steps += 1;
+            let opcode = path.cell(path.ip)?;
+            let opcode = match constant(&opcode) {
+                Some(op) => op,
+                None => return Err(format!("{}: symbolic opcode", path.ip)),
+            };
+            match opcode % 100 {
+                OPCODE_ADD | OPCODE_MULT | OPCODE_LESS_THAN | OPCODE_EQUALS => {
+                    let p1 = path.read_param(1)?;
+                    let p2 = path.read_param(2)?;
+                    let value = match opcode % 100 {
+                        OPCODE_ADD => add(p1, p2),
+                        OPCODE_MULT => mul(p1, p2),
+                        OPCODE_LESS_THAN => compare(p1, p2, true),
+                        _ => compare(p1, p2, false),
+                    };
+                    path.write_param(3, value)?;
+                    path.ip += 4;
+                }
+                OPCODE_INPUT => {
+                    let value = match self.inputs.get(path.inputs) {
+                        Some(&v) => Rc::new(Sym::Const(v)),
+                        None => self.symbol(&format!("in{}", path.inputs)),
+                    };
+                    path.inputs += 1;
+                    path.write_param(1, value)?;
+                    path.ip += 2;
+                }
+                OPCODE_OUTPUT => {
+                    let value = path.read_param(1)?;
+                    path.outputs.push(value);
+                    path.ip += 2;
+                }
+                OPCODE_JUMP_IF_TRUE | OPCODE_JUMP_IF_FALSE => {
+                    let cond = path.read_param(1)?;
+                    let target = path.read_param(2)?;
+                    let target = match constant(&target) {
+                        Some(t) if t >= 0 => t as usize,
+                        Some(t) => return Err(format!("{}: jump to negative address {}", path.ip, t)),
+                        None => return Err(format!("{}: jump to a symbolic address", path.ip)),
+                    };
+                    let when = opcode % 100 == OPCODE_JUMP_IF_TRUE;
+                    let nonzero = match constant(&cond) {
+                        Some(c) => Some(c != 0),
+                        None => path.known(&cond),
+                    };
+                    let nonzero = match nonzero {
+                        Some(nonzero) => nonzero,
+                        None => {
+                            // follow the non-zero case here and queue the other one
+                            if self.paths < self.max_paths {
+                                self.paths += 1;
+                                let mut other = path.clone();
+                                other.conditions.push((cond.clone(), false));
+                                other.ip = if !when { target } else { other.ip + 3 };
+                                pending.push(other);
+                            } else {
+                                self.truncated = true;
+                            }
+                            path.conditions.push((cond, true));
+                            true
+                        }
+                    };
+                    path.ip = if nonzero == when { target } else { path.ip + 3 };
+                }
+                OPCODE_HALT => {
+                    path.status = Status::Halted;
+                    return Ok(());
+                }
+                op => return Err(format!("{}: invalid opcode {}", path.ip, op)),
+            }
+        }
+    }
+}
+
+// finds all values within the ranges for which the linear expression
+// equals the target, trying every combination but the last symbol
+pub fn solve_linear(
+    coeffs: &BTreeMap<usize, isize>,
+    constant: isize,
+    target: isize,
+    ranges: &BTreeMap<usize, (isize, isize)>,
+) -> Result<Vec<BTreeMap<usize, isize>>, usize> {
+    for v in coeffs.keys() {
+        if !ranges.contains_key(v) {
+            return Err(*v);
+        }
+    }
+    let mut symbols = coeffs.keys().cloned().collect::<Vec<usize>>();
+    let last = match symbols.pop() {
+        Some(last) => last,
+        None if constant == target => return Ok(vec![BTreeMap::new()]),
+        None => return Ok(Vec::new()),
+    };
+
+    let mut res = Vec::new();
+    let mut values = symbols.iter().map(|v| (*v, ranges[v].0)).collect::<BTreeMap<usize, isize>>();
+    loop {
+        if symbols.iter().all(|v| values[v] < ranges[v].1) {
+            let sum = symbols.iter().try_fold(constant, |acc, v| acc.checked_add(coeffs[v].checked_mul(values[v])?));
+            if let Some(rest) = sum.and_then(|s| target.checked_sub(s)) {
+                let k = coeffs[&last];
+                let (lo, hi) = ranges[&last];
+                // isize::MIN / -1 doesn't fit, so there is no solution then
+                if let (Some(0), Some(value)) = (rest.checked_rem(k), rest.checked_div(k)) {
+                    if value >= lo && value < hi {
+                        let mut solution = values.clone();
+                        solution.insert(last, value);
+                        res.push(solution);
+                    }
+                }
+            }
+        }
+        // advance the other symbols like an odometer
+        let mut carry = true;
+        for v in &symbols {
+            let value = values.get_mut(v).unwrap();
+            *value += 1;
+            if *value < ranges[v].1 {
+                carry = false;
+                break;
+            }
+            *value = ranges[v].0;
+        }
+        if carry {
+            return Ok(res);
+        }
+    }
+}
+
+// parses "lo..hi" with an exclusive upper bound
+pub fn parse_range(s: &str) -> Option<(isize, isize)> {
+    let mut parts = s.splitn(2, "..");
+    let lo = parts.next()?.parse().ok()?;
+    let hi = parts.next()?.parse().ok()?;
+    Some((lo, hi))
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut explorer = Explorer::new();
+    let mut cells = Vec::new();
+    let mut ranges = Vec::new();
+    let mut solve = None;
+    let mut filename = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            // --cell ADDR[=NAME]
+            "--cell" => {
+                let value = value(&mut iter, arg)?;
+                let mut parts = value.splitn(2, '=');
+                let addr = parts.next().unwrap_or_default().parse::<usize>().map_err(|e| invalid(e.to_string()))?;
+                let name = parts.next().map_or_else(|| format!("m{}", addr), String::from);
+                cells.push((addr, name));
+            }
+            "--input" => explorer.inputs.push(value(&mut iter, arg)?.parse().map_err(|_| invalid("bad input value".to_string()))?),
+            // --range NAME=LO..HI
+            "--range" => {
+                let value = value(&mut iter, arg)?;
+                let mut parts = value.splitn(2, '=');
+                let name = parts.next().unwrap_or_default().to_string();
+                let range = parts.next().and_then(parse_range).ok_or_else(|| invalid(format!("bad range {}", value)))?;
+                ranges.push((name, range));
+            }
+            // --solve ADDR=VALUE
+            "--solve" => {
+                let value = value(&mut iter, arg)?;
+                let mut parts = value.splitn(2, '=').map(|p| p.parse::<isize>());
+                match (parts.next(), parts.next()) {
+                    (Some(Ok(addr)), Some(Ok(target))) if addr >= 0 => solve = Some((addr as usize, target)),
+                    _ => return Err(invalid(format!("bad target {}", value))),
+                }
+            }
+            "--max-paths" => explorer.max_paths = value(&mut iter, arg)?.parse().map_err(|_| invalid("bad path limit".to_string()))?,
+            _ => filename = Some(arg.as_str()),
+        }
+    }
+
+    let program = load_program(filename)?;
+    for (addr, _) in &cells {
+        if *addr >= program.code.len() {
+            return Err(invalid(format!("cell {} is outside of the program", addr)));
+        }
+    }
+    let initial = explorer.initial_memory(&program.code, &cells);
+    let paths = explorer.explore(&program.code, &cells);
+    let names = explorer.names.clone();
+    let mut range_map = BTreeMap::new();
+    for (name, range) in ranges {
+        match names.iter().position(|n| *n == name) {
+            Some(v) => range_map.insert(v, range),
+            None => return Err(invalid(format!("unknown symbol {}", name))),
+        };
+    }
+
+    if explorer.truncated {
+        println!("note: stopped exploring after {} paths", explorer.max_paths);
+    }
+    for (i, path) in paths.iter().enumerate() {
+        println!("path {}: {:?} after {} steps", i + 1, path.status, path.steps);
+        let mut used = BTreeSet::new();
+        for (cond, taken) in &path.conditions {
+            cond.symbols(&mut used);
+            let cond = cond.render(&names);
+            if *taken {
+                println!("  if {}", cond);
+            } else {
+                println!("  if !({})", cond);
+            }
+        }
+        for (addr, value) in path.memory.iter().enumerate() {
+            if *value == initial[addr] {
+                continue;
+            }
+            value.symbols(&mut used);
+            println!("  [{}] = {}", addr, value.render(&names));
+        }
+        for value in &path.outputs {
+            value.symbols(&mut used);
+            println!("  output {}", value.render(&names));
+        }
+        let used = used.iter().map(|&v| names[v].as_str()).collect::<Vec<&str>>();
+        println!("  depends on: {}", if used.is_empty() { "nothing".to_string() } else { used.join(", ") });
+
+        if let Some((addr, target)) = solve {
+            let value = match path.memory.get(addr) {
+                Some(value) => value,
+                None => continue,
+            };
+            let (coeffs, c) = match value.linear() {
+                Some(linear) => linear,
+                None => {
+                    println!("  [{}] is not linear, search concretely instead", addr);
+                    continue;
+                }
+            };
+            match solve_linear(&coeffs, c, target, &range_map) {
+                Ok(solutions) => {
+                    for solution in solutions {
+                        // the solution has to take this path as well
+                        let on_path = path.conditions.iter().all(|(cond, taken)| match cond.eval(&solution) {
+                            Some(v) => (v != 0) == *taken,
+                            None => true,
+                        });
+                        if on_path {
+                            let values = solution.iter().map(|(v, x)| format!("{} = {}", names[*v], x)).collect::<Vec<String>>();
+                            println!("  solution: {}", values.join(", "));
+                        }
+                    }
+                }
+                Err(v) => println!("  need --range for {}", names[v]),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `1,N,N,N` n times doubles the cell each time, 2^n leaves as a tree
+    fn doubling(n: usize) -> Vec<isize> {
+        let cell = 4 * n as isize + 1;
+        let mut code = (0..n).flat_map(|_| vec![1, cell, cell, cell]).collect::<Vec<isize>>();
+        code.extend(&[99, 0]);
+        code
+    }
+
+    #[test]
+    fn shared_subterms() {
+        let code = doubling(40);
+        let cell = code.len() - 1;
+        let symbols = [(cell, "x".to_string())];
+        let mut explorer = Explorer::new();
+        let paths = explorer.explore(&code, &symbols);
+        assert_eq!(paths.len(), 1);
+        let value = &paths[0].memory[cell];
+
+        let text = value.render(&explorer.names);
+        assert!(text.starts_with("t39 + t39 where t1 = x + x, t2 = t1 + t1,"), "{}", text);
+        assert!(text.len() < 1000);
+
+        let mut used = BTreeSet::new();
+        value.symbols(&mut used);
+        assert_eq!(used.into_iter().collect::<Vec<usize>>(), vec![0]);
+        let values = vec![(0, 1)].into_iter().collect();
+        assert_eq!(value.eval(&values), Some(1 << 40));
+        assert_eq!(value.linear(), Some((vec![(0, 1 << 40)].into_iter().collect(), 0)));
+
+        // built separately, so only equal by structure
+        let again = &explorer.explore(&code, &symbols)[0].memory[cell];
+        assert!(!Rc::ptr_eq(value, again));
+        assert!(value == again);
+        assert!(*value != explorer.initial_memory(&code, &symbols)[cell]);
+    }
+}
@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io;
+use std::io::prelude::*;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::decode::*;
+use crate::host::*;
+use crate::vm::*;
+
+const COLUMNS: usize = 10;
+// writes stay coloured for this many steps of their machine
+const RECENT: u64 = 20;
+const SPEEDS: &[u64] = &[1, 2, 5, 10, 20, 50, 100, 200, 500, 1000];
+
+const RESET: &str = "\x1b[0m";
+const REVERSE: &str = "\x1b[7m";
+const YELLOW: &str = "\x1b[33m";
+const DIM: &str = "\x1b[2m";
+const BOLD: &str = "\x1b[1m";
+
+struct Pane {
+    name: String,
+    program: IntCodeProgram,
+    steps: u64,
+    // address -> step of the last write
+    written: HashMap<usize, u64>,
+    fault: Option<Fault>,
+    // every output ever produced, the queue itself is drained in day7 mode
+    outputs: Vec<isize>,
+}
+
+impl Pane {
+    fn new(name: String, program: IntCodeProgram) -> Self {
+        Pane {
+            name,
+            program,
+            steps: 0,
+            written: HashMap::new(),
+            fault: None,
+            outputs: Vec::new(),
+        }
+    }
+
+    fn state(&self) -> &'static str {
+        if self.fault.is_some() {
+            "fault"
+        } else if self.program.halted {
+            "halted"
+        } else if self.waiting() {
+            "waiting for input"
+        } else {
+            "running"
+        }
+    }
+
+    fn waiting(&self) -> bool {
+        let op = self.program.code.get(self.program.ip).map(|op| op % 100);
+        op == Some(OPCODE_INPUT) && self.program.input.is_empty()
+    }
+
+    fn done(&self) -> bool {
+        self.fault.is_some() || self.program.halted
+    }
+
+    // one instruction; false if the machine can't make progress
+    fn step(&mut self) -> bool {
+        if self.done() || self.waiting() {
+            return false;
+        }
+        let target = decode(&self.program.code, self.program.ip).and_then(|i| i.write_target());
+        match self.program.step() {
+            Ok(State::Running) => {
+                self.steps += 1;
+                if let Some(t) = target {
+                    self.written.insert(t as usize, self.steps);
+                }
+                true
+            }
+            Ok(_) => false,
+            Err(fault) => {
+                self.fault = Some(fault);
+                false
+            }
+        }
+    }
+
+    fn render(&self, out: &mut String, rows: usize) {
+        let _ = writeln!(
+            out,
+            "{}{}{} ip {} steps {} {}\x1b[K",
+            BOLD,
+            self.name,
+            RESET,
+            self.program.ip,
+            self.steps,
+            self.fault.map_or(self.state().to_string(), |f| f.to_string())
+        );
+
+        // memory rows around the ip, disassembly on the right
+        let code = &self.program.code;
+        let total_rows = code.len().div_ceil(COLUMNS);
+        let first = (self.program.ip / COLUMNS).saturating_sub(rows / 2).min(total_rows.saturating_sub(rows));
+        let disasm = self.disassembly(rows);
+        for row in 0..rows {
+            let r = first + row;
+            if r < total_rows {
+                let _ = write!(out, "{}{:5}{} ", DIM, r * COLUMNS, RESET);
+                let start = r * COLUMNS;
+                for (i, value) in code[start..(start + COLUMNS).min(code.len())].iter().enumerate() {
+                    let addr = start + i;
+                    let recent = self.written.get(&addr).is_some_and(|&s| self.steps - s < RECENT);
+                    let style = if addr == self.program.ip {
+                        REVERSE
+                    } else if recent {
+                        YELLOW
+                    } else {
+                        ""
+                    };
+                    let _ = write!(out, "{}{:>7}{} ", style, value, RESET);
+                }
+                for _ in code.len().min((r + 1) * COLUMNS)..(r + 1) * COLUMNS {
+                    out.push_str("        ");
+                }
+            } else {
+                out.push_str(&" ".repeat(6 + 8 * COLUMNS));
+            }
+            let _ = writeln!(out, "  {}\x1b[K", disasm.get(row).map_or("", String::as_str));
+        }
+
+        let queue = |values: &[isize]| {
+            let shown = values.iter().rev().take(12).rev().map(|v| v.to_string()).collect::<Vec<String>>();
+            let more = if values.len() > 12 { "... " } else { "" };
+            format!("{}{}", more, shown.join(" "))
+        };
+        let _ = writeln!(out, "input: {}\x1b[K", queue(&self.program.input));
+        let _ = writeln!(out, "output: {}\x1b[K", queue(&self.outputs));
+    }
+
+    fn disassembly(&self, rows: usize) -> Vec<String> {
+        let mut res = Vec::new();
+        let mut addr = self.program.ip;
+        while res.len() < rows && addr < self.program.code.len() {
+            let marker = if addr == self.program.ip { ">" } else { " " };
+            match decode(&self.program.code, addr) {
+                Some(instr) => {
+                    res.push(format!("{}{:5}  {}", marker, addr, instr));
+                    addr = instr.next();
+                }
+                None => {
+                    res.push(format!("{}{:5}  {}", marker, addr, self.program.code[addr]));
+                    addr += 1;
+                }
+            }
+        }
+        res
+    }
+}
+
+// all machines, with day7's feedback wiring if there is more than one
+struct Machines {
+    panes: Vec<Pane>,
+    feedback: bool,
+}
+
+impl Machines {
+    // one instruction on every machine that can run
+    fn tick(&mut self) -> bool {
+        let mut progress = false;
+        for i in 0..self.panes.len() {
+            let before = self.panes[i].program.output.len();
+            progress |= self.panes[i].step();
+            if self.feedback && self.panes[i].program.output.len() > before {
+                // keep a copy for display, then pass it on to the next amplifier
+                let value = self.panes[i].program.output.remove(0);
+                self.panes[i].outputs.push(value);
+                let next = (i + 1) % self.panes.len();
+                self.panes[next].program.add_input(value);
+            } else if self.panes[i].program.output.len() > before {
+                let value = self.panes[i].program.output[before];
+                self.panes[i].outputs.push(value);
+            }
+        }
+        progress
+    }
+
+    fn render(&self, height: usize, speed: u64, paused: bool) -> String {
+        let mut out = String::from("\x1b[H");
+        // 3 lines of pane header and queues, 2 for the status line
+        let rows = (height.saturating_sub(2) / self.panes.len()).saturating_sub(3).clamp(1, 16);
+        for pane in &self.panes {
+            pane.render(&mut out, rows);
+        }
+        let status = if paused { "paused" } else { "running" };
+        let _ = write!(
+            out,
+            "{}{} at {} steps/s{}  space pause, s step, +/- speed, q quit\x1b[K\x1b[J",
+            BOLD, status, speed, RESET
+        );
+        // raw mode doesn't return the cursor on a newline
+        out.replace('\n', "\r\n")
+    }
+}
+
+// the terminal in raw mode for single key presses, restored on drop
+struct RawTerminal {
+    saved: String,
+}
+
+impl RawTerminal {
+    fn new() -> io::Result<Self> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        print!("\x1b[?25l\x1b[2J");
+        Ok(RawTerminal { saved: saved.trim().to_string() })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = stty(&[self.saved.as_str()]);
+        print!("\x1b[?25h\r\n");
+        let _ = io::stdout().flush();
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("stty failed, is stdin a terminal?"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn terminal_height() -> usize {
+    stty(&["size"])
+        .ok()
+        .and_then(|s| s.split_whitespace().next().and_then(|h| h.parse().ok()))
+        .unwrap_or(24)
+}
+
+pub fn run(args: &[String]) -> io::Result<()> {
+    let mut inputs = Vec::new();
+    let mut phases = None;
+    let mut speed = 3;
+    let mut filename = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--input" => {
+                let value = value(&mut iter, arg)?;
+                inputs.push(value.parse::<isize>().map_err(|_| invalid(format!("bad number {}", value)))?);
+            }
+            // --day7 9,8,7,6,5 runs one amplifier per phase in a feedback loop
+            "--day7" => {
+                let value = value(&mut iter, arg)?;
+                let parsed = value.split(',').map(|p| p.trim().parse::<isize>()).collect::<Result<Vec<isize>, _>>();
+                phases = Some(parsed.map_err(|_| invalid(format!("bad phases {}", value)))?);
+            }
+            "--speed" => {
+                let value = value(&mut iter, arg)?;
+                let steps = value.parse::<u64>().map_err(|_| invalid(format!("bad speed {}", value)))?;
+                speed = SPEEDS.iter().position(|&s| s >= steps).unwrap_or(SPEEDS.len() - 1);
+            }
+            _ => filename = Some(arg.as_str()),
+        }
+    }
+
+    let program = load_program(filename)?;
+    let mut machines = match phases {
+        Some(phases) => {
+            let mut panes = phases
+                .iter()
+                .enumerate()
+                .map(|(i, &phase)| {
+                    let mut program = program.clone();
+                    program.add_input(phase);
+                    Pane::new(format!("amplifier {} (phase {})", (b'A' + i as u8) as char, phase), program)
+                })
+                .collect::<Vec<Pane>>();
+            if let Some(first) = panes.first_mut() {
+                first.program.add_input(0);
+            }
+            Machines { panes, feedback: true }
+        }
+        None => {
+            let mut program = program;
+            program.input = inputs;
+            Machines {
+                panes: vec![Pane::new("machine".to_string(), program)],
+                feedback: false,
+            }
+        }
+    };
+
+    // key presses come from a separate thread so the machines keep running
+    let (keys, key_presses) = mpsc::channel();
+    let terminal = RawTerminal::new()?;
+    thread::spawn(move || {
+        for b in io::stdin().lock().bytes() {
+            if b.map(|b| keys.send(b).is_err()).unwrap_or(true) {
+                break;
+            }
+        }
+    });
+
+    // stty is a process of its own, so the size is only checked now and
+    // then to follow resizes
+    let mut height = terminal_height();
+    let mut measured = Instant::now();
+    let mut paused = false;
+    loop {
+        if measured.elapsed() >= Duration::from_secs(1) {
+            height = terminal_height();
+            measured = Instant::now();
+        }
+        print!("{}", machines.render(height, SPEEDS[speed], paused));
+        io::stdout().flush()?;
+
+        let delay = Duration::from_millis(1000 / SPEEDS[speed].min(50));
+        let mut single_step = false;
+        match key_presses.recv_timeout(delay) {
+            Ok(b'q') | Ok(3) => break,
+            Ok(b' ') => paused = !paused,
+            Ok(b's') | Ok(b'n') => {
+                paused = true;
+                single_step = true;
+            }
+            Ok(b'+') | Ok(b'=') => speed = (speed + 1).min(SPEEDS.len() - 1),
+            Ok(b'-') => speed = speed.saturating_sub(1),
+            _ => {}
+        }
+
+        if single_step {
+            machines.tick();
+        } else if !paused {
+            // above 50 frames a second, run several steps per frame
+            for _ in 0..(SPEEDS[speed] / 50).max(1) {
+                if !machines.tick() {
+                    paused = true;
+                    break;
+                }
+            }
+        }
+    }
+    drop(terminal);
+
+    for pane in &machines.panes {
+        println!("{}: {}, outputs {:?}", pane.name, pane.state(), pane.outputs);
+    }
+    Ok(())
+}
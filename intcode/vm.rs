@@ -0,0 +1,358 @@
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::cell::*;
+use crate::coverage::Coverage;
+use crate::parse::*;
+use crate::registry::*;
+
+pub const OPCODE_ADD: isize = 1;
+pub const OPCODE_MULT: isize = 2;
+pub const OPCODE_INPUT: isize = 3;
+pub const OPCODE_OUTPUT: isize = 4;
+pub const OPCODE_JUMP_IF_TRUE: isize = 5;
+pub const OPCODE_JUMP_IF_FALSE: isize = 6;
+pub const OPCODE_LESS_THAN: isize = 7;
+pub const OPCODE_EQUALS: isize = 8;
+pub const OPCODE_HALT: isize = 99;
+
+pub const PMODE_POSITION: isize = 0;
+pub const PMODE_IMMEDIATE: isize = 1;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum State {
+    Running,
+    WaitingForInput,
+    Halted,
+}
+
+// why the machine can't go on, with the address of the instruction.
+// values that don't fit in an isize are clamped
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Fault {
+    BadOpcode { ip: usize, opcode: isize },
+    BadMode { ip: usize, opcode: isize },
+    OutOfBounds { ip: usize, addr: isize },
+    Overflow { ip: usize },
+    // an extension opcode gave the wrong number of results
+    BadExtension { ip: usize, opcode: isize },
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::BadOpcode { ip, opcode } => write!(f, "bad opcode {} at {}", opcode, ip),
+            Fault::BadMode { ip, opcode } => {
+                write!(f, "bad parameter mode in {} at {}", opcode, ip)
+            }
+            Fault::OutOfBounds { ip, addr } => {
+                write!(f, "address {} out of bounds at {}", addr, ip)
+            }
+            Fault::Overflow { ip } => write!(f, "arithmetic overflow at {}", ip),
+            Fault::BadExtension { ip, opcode } => {
+                write!(f, "extension opcode {} failed at {}", opcode, ip)
+            }
+        }
+    }
+}
+
+impl Error for Fault {}
+
+#[derive(Clone)]
+pub struct IntCodeProgram<C = isize> {
+    pub code: Vec<C>,
+    pub ip: usize, // instruction pointer
+    pub halted: bool,
+    // report overflow as a fault instead of wrapping around
+    pub checked: bool,
+    // reject mode digits that mean nothing, like an immediate write target
+    pub strict: bool,
+    pub input: Vec<C>,
+    pub output: Vec<C>,
+    // extra opcodes, tried when the built-in ones don't match
+    pub extensions: Registry<C>,
+    // executed addresses and branch directions, when recording
+    pub coverage: Option<Coverage>,
+}
+
+impl<C: Cell> IntCodeProgram<C> {
+    pub fn new(code: Vec<C>) -> Self {
+        IntCodeProgram {
+            code,
+            ip: 0,
+            halted: false,
+            checked: false,
+            strict: false,
+            input: Vec::new(),
+            output: Vec::new(),
+            extensions: Registry::new(),
+            coverage: None,
+        }
+    }
+
+    // executes a single instruction; does not advance if input is missing.
+    // unless checked, arithmetic wraps around like release builds of the
+    // day solutions do
+    pub fn step(&mut self) -> Result<State, Fault> {
+        let ip = self.ip;
+        let halted = self.halted;
+        let state = self.instruction()?;
+        if let Some(coverage) = &mut self.coverage {
+            if !halted && state != State::WaitingForInput {
+                coverage.hit(ip);
+            }
+        }
+        Ok(state)
+    }
+
+    fn instruction(&mut self) -> Result<State, Fault> {
+        if self.halted {
+            return Ok(State::Halted);
+        }
+        let opcode = self.opcode()?;
+        if self.strict {
+            self.check_modes(opcode)?;
+        }
+        match opcode % 100 {
+            OPCODE_ADD => {
+                let p1 = self.read_param(1)?;
+                let p2 = self.read_param(2)?;
+                let sum = self.arithmetic(p1.checked_add(&p2), || p1.wrapping_add(&p2))?;
+                self.write_param(3, sum)?;
+                self.ip += 4;
+            }
+            OPCODE_MULT => {
+                let p1 = self.read_param(1)?;
+                let p2 = self.read_param(2)?;
+                let product = self.arithmetic(p1.checked_mul(&p2), || p1.wrapping_mul(&p2))?;
+                self.write_param(3, product)?;
+                self.ip += 4;
+            }
+            OPCODE_INPUT => {
+                if self.input.is_empty() {
+                    return Ok(State::WaitingForInput);
+                }
+                let input = self.input[0].clone();
+                self.write_param(1, input)?;
+                self.input.remove(0);
+                self.ip += 2;
+            }
+            OPCODE_OUTPUT => {
+                let p1 = self.read_param(1)?;
+                self.output.push(p1);
+                self.ip += 2;
+            }
+            OPCODE_JUMP_IF_TRUE => {
+                let condition = self.read_param(1)?;
+                let target = self.read_param(2)?;
+                self.jump(condition != C::from_isize(0), target)?;
+            }
+            OPCODE_JUMP_IF_FALSE => {
+                let condition = self.read_param(1)?;
+                let target = self.read_param(2)?;
+                self.jump(condition == C::from_isize(0), target)?;
+            }
+            OPCODE_LESS_THAN => {
+                let p1 = self.read_param(1)?;
+                let p2 = self.read_param(2)?;
+                self.write_param(3, C::from_isize((p1 < p2) as isize))?;
+                self.ip += 4;
+            }
+            OPCODE_EQUALS => {
+                let p1 = self.read_param(1)?;
+                let p2 = self.read_param(2)?;
+                self.write_param(3, C::from_isize((p1 == p2) as isize))?;
+                self.ip += 4;
+            }
+            OPCODE_HALT => {
+                self.halted = true;
+                return Ok(State::Halted);
+            }
+            op => match self.extensions.get(op) {
+                Some(extension) => return self.extension(opcode, extension.clone()),
+                None => return Err(Fault::BadOpcode { ip: self.ip, opcode }),
+            },
+        }
+        Ok(State::Running)
+    }
+
+    fn extension(&mut self, opcode: isize, extension: Extension<C>) -> Result<State, Fault> {
+        let ip = self.ip;
+        let mut args = Vec::new();
+        let mut writes = Vec::new();
+        for (i, role) in extension.params.iter().enumerate() {
+            let param_num = i as u32 + 1;
+            match role {
+                Role::Read => args.push(self.read_param(param_num)?),
+                Role::Write => writes.push(param_num),
+            }
+        }
+        let results = match (extension.handler)(self, &args)? {
+            Some(results) => results,
+            None => return Ok(State::WaitingForInput),
+        };
+        if results.len() != writes.len() {
+            return Err(Fault::BadExtension { ip, opcode });
+        }
+        // parameters belong to this instruction even if the handler jumped
+        let next = self.ip;
+        self.ip = ip;
+        for (param_num, value) in writes.into_iter().zip(results) {
+            self.write_param(param_num, value)?;
+        }
+        self.ip = if next == ip {
+            ip + 1 + extension.params.len()
+        } else {
+            next
+        };
+        Ok(State::Running)
+    }
+
+    // write targets must be in position mode, and there must be no mode
+    // digits past the last parameter, so 1099 and 11101 are both rejected
+    fn check_modes(&self, opcode: isize) -> Result<(), Fault> {
+        let (params, writes) = match opcode % 100 {
+            OPCODE_ADD | OPCODE_MULT | OPCODE_LESS_THAN | OPCODE_EQUALS => (3, vec![3]),
+            OPCODE_INPUT => (1, vec![1]),
+            OPCODE_OUTPUT => (1, vec![]),
+            OPCODE_JUMP_IF_TRUE | OPCODE_JUMP_IF_FALSE => (2, vec![]),
+            OPCODE_HALT => (0, vec![]),
+            op => match self.extensions.get(op) {
+                Some(extension) => {
+                    let writes = (1..)
+                        .zip(&extension.params)
+                        .filter(|(_, role)| **role == Role::Write)
+                        .map(|(n, _)| n)
+                        .collect();
+                    (extension.params.len() as u32, writes)
+                }
+                // step reports the bad opcode
+                None => return Ok(()),
+            },
+        };
+        let modes = opcode / 100;
+        let bad_write = writes
+            .iter()
+            .any(|n| (modes / 10isize.pow(n - 1)) % 10 != PMODE_POSITION);
+        if bad_write || modes / 10isize.pow(params) != 0 {
+            return Err(Fault::BadMode {
+                ip: self.ip,
+                opcode,
+            });
+        }
+        Ok(())
+    }
+
+    // runs until the program halts or needs more input
+    pub fn execute(&mut self) -> Result<State, Fault> {
+        loop {
+            let state = self.step()?;
+            if state != State::Running {
+                return Ok(state);
+            }
+        }
+    }
+
+    fn cell(&self, addr: isize) -> Result<C, Fault> {
+        match self.code.get(addr as usize) {
+            Some(value) if addr >= 0 => Ok(value.clone()),
+            _ => Err(Fault::OutOfBounds { ip: self.ip, addr }),
+        }
+    }
+
+    fn opcode(&self) -> Result<isize, Fault> {
+        let raw = self.cell(self.ip as isize)?;
+        raw.to_isize().ok_or(Fault::BadOpcode {
+            ip: self.ip,
+            opcode: clamp(&raw),
+        })
+    }
+
+    fn read_param(&self, param_num: u32) -> Result<C, Fault> {
+        let opcode = self.opcode()?;
+        let mode = (opcode / 10isize.pow(param_num + 1)) % 10;
+        let param = self.cell(self.ip as isize + param_num as isize)?;
+        match mode {
+            PMODE_POSITION => self.cell(clamp(&param)),
+            PMODE_IMMEDIATE => Ok(param),
+            _ => Err(Fault::BadMode { ip: self.ip, opcode }),
+        }
+    }
+
+    fn write_param(&mut self, param_num: u32, value: C) -> Result<(), Fault> {
+        let out = clamp(&self.cell(self.ip as isize + param_num as isize)?);
+        self.cell(out)?;
+        self.code[out as usize] = value;
+        Ok(())
+    }
+
+    fn arithmetic(&self, checked: Option<C>, wrapping: impl FnOnce() -> C) -> Result<C, Fault> {
+        match checked {
+            Some(value) => Ok(value),
+            None if self.checked => Err(Fault::Overflow { ip: self.ip }),
+            None => Ok(wrapping()),
+        }
+    }
+
+    fn jump(&mut self, taken: bool, target: C) -> Result<(), Fault> {
+        let target = clamp(&target);
+        // a jump that faults doesn't count
+        if let Some(coverage) = self.coverage.as_mut().filter(|_| !taken || target >= 0) {
+            coverage.branch(self.ip, taken);
+        }
+        if !taken {
+            self.ip += 3;
+        } else if target < 0 {
+            return Err(Fault::OutOfBounds {
+                ip: self.ip,
+                addr: target,
+            });
+        } else {
+            self.ip = target as usize;
+        }
+        Ok(())
+    }
+
+    pub fn add_input(&mut self, new_input: C) {
+        self.input.push(new_input)
+    }
+
+    pub fn take_output(&mut self) -> Option<C> {
+        if self.output.is_empty() {
+            None
+        } else {
+            Some(self.output.remove(0))
+        }
+    }
+}
+
+// values too large for an isize are never valid addresses anyway
+fn clamp<C: Cell>(value: &C) -> isize {
+    value.to_isize().unwrap_or(if *value < C::from_isize(0) {
+        isize::MIN
+    } else {
+        isize::MAX
+    })
+}
+
+impl<C: Cell> FromStr for IntCodeProgram<C>
+where
+    C::Err: fmt::Display,
+{
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(IntCodeProgram::new(parse_cells(s)?))
+    }
+}
+
+pub fn format_code(code: &[isize]) -> String {
+    code.iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}